@@ -0,0 +1,79 @@
+//! Definitions for the named capture groups of a regex.
+
+use crate::combine::rewrite_references;
+use crate::nfa::Builder;
+use crate::schema::{check_tree_size, root_schema, take_definitions};
+use crate::{build_nfa, to_json_schema, Draft, Encoding, Error, MatchMode, Options};
+use regex_syntax::hir::{GroupKind, Hir, HirKind};
+use schemars::schema::{RootSchema, Schema};
+
+/// Add a definition `capture.{name}` to `schema` for each named capture group in `hir`, accepting
+/// the encodings of the strings the group matches on its own. The definitions of the group's
+/// schema are added as well, renamed to `capture.{name}.{definition}`.
+///
+/// Each group is converted with `builder`, which must have been created for `hir`, and with
+/// `options`, except that it has to match the whole input.
+pub(crate) fn add_group_definitions(
+    mut schema: RootSchema,
+    builder: &Builder,
+    hir: &Hir,
+    options: &Options,
+) -> Result<RootSchema, Error> {
+    let draft = options.draft;
+    let group_options = Options {
+        match_mode: MatchMode::Full,
+        ..options.clone()
+    };
+    let mut definitions = take_definitions(&mut schema, draft);
+    let mut groups = vec![];
+    named_groups(hir, &mut groups);
+    for (name, group) in groups {
+        let nfa = build_nfa(builder, group, &group_options)?;
+        if group_options.encoding == Encoding::Tree {
+            check_tree_size(&nfa, &group_options)?;
+        }
+        let mut group_schema = to_json_schema(&nfa, &group_options);
+        let prefix = format!("capture.{}", name);
+        let rename = |definition: &str| format!("{}.{}", prefix, definition);
+        for (definition, value) in take_definitions(&mut group_schema, draft) {
+            definitions.insert(rename(&definition), renamed(value, draft, rename));
+        }
+        let mut root = renamed(group_schema.schema.into(), draft, rename).into_object();
+        let metadata = root.metadata();
+        metadata.title = Some(name.to_string());
+        metadata.description = Some(format!(
+            "The strings matched by the capture group `{}`.",
+            name
+        ));
+        definitions.insert(prefix, root.into());
+    }
+    Ok(root_schema(schema.schema.into(), definitions, draft))
+}
+
+/// `schema` with each reference to a definition `name` replaced by one to `rename(name)`.
+fn renamed(schema: Schema, draft: Draft, rename: impl Fn(&str) -> String) -> Schema {
+    let mut value = serde_json::to_value(schema).unwrap();
+    rewrite_references(&mut value, draft, &mut |name| rename(name));
+    serde_json::from_value(value).unwrap()
+}
+
+/// Collect the named capture groups in `hir` with their names, in pre-order.
+fn named_groups<'a>(hir: &'a Hir, groups: &mut Vec<(&'a str, &'a Hir)>) {
+    match hir.kind() {
+        HirKind::Group(group) => {
+            if let GroupKind::CaptureName { name, .. } = &group.kind {
+                groups.push((name, &group.hir));
+            }
+            named_groups(&group.hir, groups);
+        }
+        HirKind::Repetition(rep) => named_groups(&rep.hir, groups),
+        HirKind::Alternation(xs) | HirKind::Concat(xs) => {
+            xs.iter().for_each(|x| named_groups(x, groups))
+        }
+        HirKind::Empty
+        | HirKind::Literal(_)
+        | HirKind::Class(_)
+        | HirKind::Anchor(_)
+        | HirKind::WordBoundary(_) => {}
+    }
+}
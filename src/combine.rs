@@ -0,0 +1,148 @@
+//! Combination of the schemas of several fields into one document.
+
+use crate::schema::{reference, root_schema, unescape_pointer};
+use crate::Draft;
+use schemars::schema::{InstanceType, ObjectValidation, RootSchema, Schema, SchemaObject};
+use schemars::Map;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// Combine the schemas generated for several fields into one, whose root accepts objects with
+/// each field's encoded input under its name. All schemas must be generated for `draft`.
+///
+/// The definitions of field `f` are renamed to `f.{name}`, so that they don't collide, and then
+/// definitions which are structurally identical, up to the definitions they refer to, are merged
+/// into one, named after its first occurrence.
+///
+/// # Panics
+///
+/// If two fields have the same name.
+pub fn combine_schemas(
+    schemas: impl IntoIterator<Item = (String, RootSchema)>,
+    draft: Draft,
+) -> RootSchema {
+    let mut names = vec![];
+    let mut definitions = vec![];
+    let mut roots = vec![];
+    let mut fields = HashSet::new();
+    for (field, schema) in schemas {
+        assert!(fields.insert(field.clone()), "duplicate field {:?}", field);
+        let mut root = serde_json::to_value(schema.schema).unwrap();
+        let field_definitions = match draft {
+            Draft::Draft7 => serde_json::to_value(schema.definitions).unwrap(),
+            Draft::Draft201909 | Draft::Draft202012 => root
+                .as_object_mut()
+                .unwrap()
+                .remove(draft.definitions_keyword())
+                .unwrap_or_default(),
+        };
+        let rename = |name: &str| format!("{}.{}", field, name);
+        rewrite_references(&mut root, draft, &mut |name| rename(name));
+        if let Value::Object(field_definitions) = field_definitions {
+            for (name, mut definition) in field_definitions {
+                rewrite_references(&mut definition, draft, &mut |name| rename(name));
+                names.push(rename(&name));
+                definitions.push(definition);
+            }
+        }
+        roots.push((field, root));
+    }
+
+    // Merge the definitions by partition refinement: start with all of them in one class, and
+    // split classes by what their definitions look like with references replaced by the classes
+    // of their targets, until no class is split.
+    let index: HashMap<String, usize> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.clone(), i))
+        .collect();
+    let mut class = vec![0; names.len()];
+    let mut num_classes = 1;
+    loop {
+        let mut signatures = HashMap::new();
+        let refined: Vec<usize> = definitions
+            .iter()
+            .zip(&class)
+            .map(|(definition, &c)| {
+                let mut signature = definition.clone();
+                rewrite_references(&mut signature, draft, &mut |name| {
+                    class[index[name]].to_string()
+                });
+                let next = signatures.len();
+                *signatures.entry((c, signature.to_string())).or_insert(next)
+            })
+            .collect();
+        class = refined;
+        if signatures.len() == num_classes {
+            break;
+        }
+        num_classes = signatures.len();
+    }
+
+    let mut representatives = HashMap::new();
+    for (i, &c) in class.iter().enumerate() {
+        representatives.entry(c).or_insert(i);
+    }
+    let merge = |value: &mut Value| {
+        rewrite_references(value, draft, &mut |name| {
+            names[representatives[&class[index[name]]]].clone()
+        })
+    };
+    let mut merged = Map::new();
+    for (i, mut definition) in definitions.into_iter().enumerate() {
+        if representatives[&class[i]] == i {
+            merge(&mut definition);
+            merged.insert(names[i].clone(), from_value(definition));
+        }
+    }
+    let mut properties = Map::new();
+    for (field, mut root) in roots {
+        merge(&mut root);
+        properties.insert(field, from_value(root));
+    }
+
+    let object = SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(ObjectValidation {
+            required: properties.keys().cloned().collect(),
+            properties,
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    root_schema(object.into(), merged, draft)
+}
+
+/// Replace each reference to a definition `name` in `value` by a reference to `rename(name)`.
+pub(crate) fn rewrite_references(
+    value: &mut Value,
+    draft: Draft,
+    rename: &mut impl FnMut(&str) -> String,
+) {
+    let prefix = format!("#/{}/", draft.definitions_keyword());
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                match (key.as_str(), &*value) {
+                    ("$ref", Value::String(target)) if target.starts_with(&prefix) => {
+                        let name = rename(&unescape_pointer(&target[prefix.len()..]));
+                        *value = reference(&name, draft).reference.unwrap().into();
+                    }
+                    // Values of these are data, not schemas.
+                    ("const" | "enum", _) => {}
+                    _ => rewrite_references(value, draft, rename),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                rewrite_references(item, draft, rename);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn from_value(value: Value) -> Schema {
+    serde_json::from_value(value).unwrap()
+}
@@ -0,0 +1,187 @@
+//! Conversion of NFAs to deterministic automata.
+
+use crate::error::Limit;
+use crate::nfa::GroupLabel;
+use crate::{Error, Nfa, Options, State, Transition};
+use regex_syntax::hir::ClassUnicode;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+/// Convert `nfa`, which must not contain `Goto` transitions, to an equivalent deterministic
+/// automaton using the subset construction.
+///
+/// The result is again an `Nfa`, with the transitions out of each state consuming disjoint sets
+/// of characters. Only states reachable from the start state are kept. The result is checked
+/// against the size limits in `options` as it grows.
+pub fn determinize(nfa: &Nfa, options: &Options) -> Result<Nfa, Error> {
+    determinize_subset(nfa, vec![nfa.start()], options)
+}
+
+/// Like `determinize`, but starting from a set of states (which must be sorted).
+fn determinize_subset(
+    nfa: &Nfa,
+    start_subset: Vec<State>,
+    options: &Options,
+) -> Result<Nfa, Error> {
+    let mut dfa = Nfa::default();
+    let mut subsets = HashMap::new();
+    let mut queue = VecDeque::new();
+
+    let dfa_start = dfa.new_state();
+    subsets.insert(start_subset.clone(), dfa_start);
+    queue.push_back((start_subset, dfa_start));
+
+    while let Some((subset, from)) = queue.pop_front() {
+        let mut transitions: Vec<(ClassUnicode, Vec<State>)> = vec![];
+        for (class, targets) in split_transitions(nfa, &subset) {
+            let targets: Vec<State> = targets.into_iter().collect();
+            match transitions.iter_mut().find(|(_, t)| *t == targets) {
+                Some((existing, _)) => existing.union(&class),
+                None => transitions.push((class, targets)),
+            }
+        }
+        // New states are numbered in the order of the characters leading to them, so that
+        // equivalent automata come out numbered the same way.
+        transitions.sort_by_key(|(class, _)| class.ranges()[0].start());
+        for (class, target_subset) in transitions {
+            let to = *subsets.entry(target_subset.clone()).or_insert_with(|| {
+                let to = dfa.new_state();
+                queue.push_back((target_subset.clone(), to));
+                to
+            });
+            let label = group_label(nfa, &subset, &class, &target_subset);
+            dfa.add_labelled_transition(from, Transition::consume(class, to), label);
+        }
+        if subset.iter().any(|&s| nfa.is_accepting(s)) {
+            dfa.add_transition(from, Transition::Accept);
+        }
+        dfa.check_size(Limit::DfaStates, options)?;
+    }
+
+    Ok(dfa)
+}
+
+/// The combined group labels of the transitions of `nfa` from `subset` to `target_subset` which
+/// consume characters from `class`.
+fn group_label(
+    nfa: &Nfa,
+    subset: &[State],
+    class: &ClassUnicode,
+    target_subset: &[State],
+) -> GroupLabel {
+    let mut label = GroupLabel::Unlabelled;
+    for &s in subset {
+        for (i, t) in nfa.transitions(s).iter().enumerate() {
+            if let (Some(mut common), Some(target)) = (t.unicode_class(), t.target()) {
+                common.intersect(class);
+                if !common.ranges().is_empty() && target_subset.contains(&target) {
+                    label.merge(nfa.transition_group_label(s, i));
+                }
+            }
+        }
+    }
+    label
+}
+
+/// Convert `nfa`, which must not contain `Goto` transitions, to the minimal equivalent
+/// deterministic automaton.
+///
+/// This uses Brzozowski's algorithm: determinizing the reverse of an automaton whose reverse is
+/// deterministic yields the minimal DFA, so determinizing twice, reversing before each step, does
+/// the job. Both determinizations are checked against the size limits in `options`.
+pub fn minimize(nfa: &Nfa, options: &Options) -> Result<Nfa, Error> {
+    let (reversed, starts) = reverse(nfa);
+    let dfa = determinize_subset(&reversed, starts, options)?;
+    let (reversed, starts) = reverse(&dfa);
+    let mut dfa = determinize_subset(&reversed, starts, options)?;
+    copy_group_labels(nfa, &mut dfa);
+    Ok(dfa)
+}
+
+/// Label each transition of `dfa`, which must be deterministic and equivalent to `nfa`, with the
+/// group labels of the transitions of `nfa` which consume the same characters of the same inputs.
+/// Reversing an automaton loses the meaning of its labels, so they can't be carried through
+/// `minimize` like through `determinize`.
+fn copy_group_labels(nfa: &Nfa, dfa: &mut Nfa) {
+    let mut seen = HashSet::from([(nfa.start(), dfa.start())]);
+    let mut stack = vec![(nfa.start(), dfa.start())];
+    while let Some((state, dfa_state)) = stack.pop() {
+        let mut labels = vec![];
+        for (i, t) in nfa.transitions(state).iter().enumerate() {
+            let (class, target) = match (t.unicode_class(), t.target()) {
+                (Some(class), Some(target)) => (class, target),
+                _ => continue,
+            };
+            for (j, u) in dfa.transitions(dfa_state).iter().enumerate() {
+                if let (Some(mut common), Some(dfa_target)) = (u.unicode_class(), u.target()) {
+                    common.intersect(&class);
+                    if !common.ranges().is_empty() {
+                        labels.push((j, nfa.transition_group_label(state, i)));
+                        if seen.insert((target, dfa_target)) {
+                            stack.push((target, dfa_target));
+                        }
+                    }
+                }
+            }
+        }
+        for (j, label) in labels {
+            dfa.add_transition_group_label(dfa_state, j, label);
+        }
+    }
+}
+
+/// Build an automaton accepting the reverses of the strings accepted by `nfa`, which must not
+/// contain `Goto` transitions. Returns the automaton and its (sorted) set of start states, which
+/// are the accepting states of `nfa`.
+fn reverse(nfa: &Nfa) -> (Nfa, Vec<State>) {
+    let mut reversed = Nfa::default();
+    for _ in 0..nfa.num_states() {
+        reversed.new_state();
+    }
+    for from in 0..nfa.num_states() {
+        for t in nfa.transitions(from) {
+            if let (Some(class), Some(to)) = (t.unicode_class(), t.target()) {
+                reversed.add_transition(to, Transition::consume(class, from));
+            }
+        }
+    }
+    reversed.add_transition(nfa.start(), Transition::Accept);
+    let starts = (0..nfa.num_states())
+        .filter(|&s| nfa.is_accepting(s))
+        .collect();
+    (reversed, starts)
+}
+
+/// Split the characters consumed by transitions out of `subset` into disjoint classes, each paired
+/// with the set of states reachable by consuming any character from that class.
+fn split_transitions(nfa: &Nfa, subset: &[State]) -> Vec<(ClassUnicode, BTreeSet<State>)> {
+    let mut parts: Vec<(ClassUnicode, BTreeSet<State>)> = vec![];
+    for t in subset.iter().flat_map(|&s| nfa.transitions(s)) {
+        let (mut remaining, target) = match (t.unicode_class(), t.target()) {
+            (Some(class), Some(target)) => (class, target),
+            _ => continue,
+        };
+        let mut new_parts = vec![];
+        for (class, targets) in parts {
+            let mut common = class.clone();
+            common.intersect(&remaining);
+            if common.ranges().is_empty() {
+                new_parts.push((class, targets));
+                continue;
+            }
+            let mut rest = class;
+            rest.difference(&remaining);
+            remaining.difference(&common);
+            if !rest.ranges().is_empty() {
+                new_parts.push((rest, targets.clone()));
+            }
+            let mut targets = targets;
+            targets.insert(target);
+            new_parts.push((common, targets));
+        }
+        if !remaining.ranges().is_empty() {
+            new_parts.push((remaining, BTreeSet::from([target])));
+        }
+        parts = new_parts;
+    }
+    parts
+}
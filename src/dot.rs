@@ -0,0 +1,196 @@
+//! Output of automata in Graphviz DOT format.
+
+use crate::encoding::longest_run;
+use crate::{Nfa, State, Transition};
+use regex_syntax::hir::{ClassUnicode, ClassUnicodeRange};
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+/// Write `nfa` as a DOT graph. States are labelled with numbers in breadth-first order from the
+/// start state, which for automata returned by `generate_nfa` are the state numbers themselves,
+/// and accepting states are drawn with a double circle.
+///
+/// The transitions between two states consuming characters are merged into one edge, labelled with
+/// the characters in character class syntax. `Goto` transitions are labelled `ε`. With `bytes`, for
+/// automata built with `Options::bytes`, the characters are byte values, and those outside ASCII
+/// are labelled as escapes such as `\xC3`.
+pub fn write_dot(nfa: &Nfa, bytes: bool, out: &mut impl Write) -> io::Result<()> {
+    write_graph(nfa, None, bytes, out)
+}
+
+/// Like `write_dot`, but highlighting the path `input` takes through `nfa`: an accepting run if
+/// there is one, and otherwise a run on the longest prefix of `input` which `nfa` can consume.
+/// With `bytes`, the run is on the UTF-8 encoding of `input`.
+///
+/// The path is only meaningful for automata without `Goto` or `Assert` transitions, such as those
+/// returned by `generate_nfa`.
+pub fn write_dot_with_path(
+    nfa: &Nfa,
+    input: &str,
+    bytes: bool,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    write_graph(nfa, Some(input), bytes, out)
+}
+
+fn write_graph(
+    nfa: &Nfa,
+    input: Option<&str>,
+    bytes: bool,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let state_mapping = nfa.breadth_first_numbering();
+    let mut path_states = HashSet::new();
+    let mut path_edges = HashSet::new();
+    writeln!(out, "digraph {{")?;
+    writeln!(out, "rankdir=LR")?;
+    if let Some(input) = input {
+        let chars: Vec<char> = if bytes {
+            input.bytes().map(char::from).collect()
+        } else {
+            input.chars().collect()
+        };
+        let run = longest_run(nfa, &chars);
+        let consumed = run.len() - 1;
+        let accepted = consumed == chars.len() && nfa.is_accepting(*run.last().unwrap());
+        let verdict = if accepted {
+            "accepted".to_string()
+        } else if consumed == chars.len() {
+            "rejected".to_string()
+        } else {
+            let unit = if bytes { "bytes" } else { "characters" };
+            format!("rejected after {} of {} {}", consumed, chars.len(), unit)
+        };
+        writeln!(
+            out,
+            "label=\"{}\"",
+            escape(&format!("{:?}: {}", input, verdict))
+        )?;
+        path_edges.extend(run.windows(2).map(|w| (w[0], w[1])));
+        path_states.extend(run);
+    }
+    writeln!(out, "\"\" [shape=none]")?;
+    writeln!(out, "\"\" -> {}", nfa.start())?;
+    for (from, number) in state_mapping.iter().enumerate() {
+        let shape = if nfa.is_accepting(from) {
+            "doublecircle"
+        } else {
+            "circle"
+        };
+        let style = if path_states.contains(&from) {
+            " style=filled fillcolor=lightblue"
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "{} [label=\"{}\" shape={}{}]",
+            from, number, shape, style
+        )?;
+
+        // Merge the consuming transitions by target, keeping the order of their first occurrence.
+        let mut consumed: Vec<(State, ClassUnicode)> = vec![];
+        for t in nfa.transitions(from) {
+            let label = match t {
+                Transition::Goto(_) => "ε".to_string(),
+                Transition::Assert(look, _) => look.as_str().to_string(),
+                Transition::Consume(_, to) | Transition::ConsumeClass(_, to) => {
+                    match consumed.iter_mut().find(|(target, _)| target == to) {
+                        Some((_, class)) => class.union(&t.unicode_class().unwrap()),
+                        None => consumed.push((*to, t.unicode_class().unwrap())),
+                    }
+                    continue;
+                }
+                Transition::Accept => continue,
+            };
+            writeln!(
+                out,
+                "{} -> {} [label=\"{}\"]",
+                from,
+                t.target().unwrap(),
+                escape(&label)
+            )?;
+        }
+        for (to, class) in consumed {
+            let style = if path_edges.contains(&(from, to)) {
+                " color=blue penwidth=2"
+            } else {
+                ""
+            };
+            writeln!(
+                out,
+                "{} -> {} [label=\"{}\"{}]",
+                from,
+                to,
+                escape(&class_label(&class, bytes)),
+                style
+            )?;
+        }
+    }
+    writeln!(out, "}}")
+}
+
+/// A label for the characters of `class`: a single character as itself, and otherwise a character
+/// class with ranges such as `a-z`, negated if that's shorter. With `bytes`, the characters are
+/// byte values, and the class is negated among those.
+fn class_label(class: &ClassUnicode, bytes: bool) -> String {
+    let mut negated = class.clone();
+    negated.negate();
+    if bytes {
+        negated.intersect(&ClassUnicode::new([ClassUnicodeRange::new('\0', '\u{FF}')]));
+    }
+    if negated.ranges().is_empty() {
+        return "any".to_string();
+    }
+    if let [r] = class.ranges() {
+        if r.start() == r.end() {
+            return char_label(r.start(), bytes);
+        }
+    }
+    let (prefix, class) = if negated.ranges().len() < class.ranges().len() {
+        ("^", &negated)
+    } else {
+        ("", class)
+    };
+    let mut label = format!("[{}", prefix);
+    for r in class.iter() {
+        label.push_str(&char_label(r.start(), bytes));
+        if r.end() != r.start() {
+            if r.end() as u32 > r.start() as u32 + 1 {
+                label.push('-');
+            }
+            label.push_str(&char_label(r.end(), bytes));
+        }
+    }
+    label.push(']');
+    label
+}
+
+/// A visible representation of `c`: space is shown as `␣`, and other whitespace and control
+/// characters are escaped. With `bytes`, `c` is a byte value, and one outside ASCII is shown as
+/// `\xNN`.
+fn char_label(c: char, bytes: bool) -> String {
+    match c {
+        _ if bytes && !c.is_ascii() => format!("\\x{:02X}", c as u32),
+        ' ' => "␣".to_string(),
+        _ if c.is_whitespace() || c.is_control() => c.escape_default().to_string(),
+        _ => c.to_string(),
+    }
+}
+
+/// Escape `s` for use in a quoted DOT string.
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,362 @@
+//! Encoding of input strings as JSON documents.
+
+use crate::{generate_nfa, Encoding, Error, Nfa, Options, State};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Encodes inputs as the schema `compile` generates for a regex expects, like `encode`, but with
+/// the regex compiled once rather than for each input.
+pub struct Encoder {
+    /// The automaton splitting inputs, for `Encoding::Chunked` and `Encoding::Tree`.
+    nfa: Option<Nfa>,
+    encoding: Encoding,
+    bytes: bool,
+}
+
+impl Encoder {
+    /// Prepare to encode inputs for the schema `compile` generates for `regex` with `options`.
+    pub fn new(regex: &str, options: &Options) -> Result<Self, Error> {
+        let nfa = match options.encoding {
+            Encoding::Cons => None,
+            Encoding::Chunked | Encoding::Tree => Some(generate_nfa(regex, options)?),
+        };
+        Ok(Encoder {
+            nfa,
+            encoding: options.encoding,
+            bytes: options.bytes,
+        })
+    }
+
+    /// Encode `input`. With `Options::bytes`, the UTF-8 encoding of `input` is encoded.
+    pub fn encode(&self, input: &str) -> Value {
+        if self.bytes {
+            return self.encode_bytes(input.as_bytes());
+        }
+        match (self.encoding, &self.nfa) {
+            (Encoding::Chunked, Some(nfa)) => encode_chunked(nfa, input),
+            (Encoding::Tree, Some(nfa)) => encode_tree(nfa, input),
+            _ => encode_input(input),
+        }
+    }
+
+    /// Encode `input`, which needn't be UTF-8, for byte mode (see `Options::bytes`).
+    pub fn encode_bytes(&self, input: &[u8]) -> Value {
+        match (self.encoding, &self.nfa) {
+            (Encoding::Chunked, Some(nfa)) => encode_chunked_bytes(nfa, input),
+            (Encoding::Tree, Some(nfa)) => encode_tree_bytes(nfa, input),
+            _ => encode_input_bytes(input),
+        }
+    }
+}
+
+/// Encode `input` for `Encoding::Cons`: each character is paired with the encoding of the rest of
+/// the input, so `"ab"` becomes `["a", ["b", []]]`.
+pub fn encode_input(input: &str) -> Value {
+    cons(input.chars().map(char_value))
+}
+
+/// Encode `input` for `Encoding::Cons` in byte mode (see `Options::bytes`), where each byte is
+/// represented by its value, so `b"ab"` becomes `[97, [98, []]]`.
+pub fn encode_input_bytes(input: &[u8]) -> Value {
+    cons(input.iter().map(|&b| b.into()))
+}
+
+fn cons(symbols: impl DoubleEndedIterator<Item = Value>) -> Value {
+    symbols.rfold(Vec::<()>::new().into(), |next, symbol| {
+        vec![symbol, next].into()
+    })
+}
+
+fn char_value(c: char) -> Value {
+    c.to_string().into()
+}
+
+/// Encode `input` for `Encoding::Chunked`. The input is split where the run of `nfa` on it leaves
+/// a literal run, so `"ab"` becomes `["ab", []]` for the regex `ab`. If `nfa` doesn't accept
+/// `input`, each chunk is a single character, and the result is rejected by the schema.
+pub fn encode_chunked(nfa: &Nfa, input: &str) -> Value {
+    let chars: Vec<char> = input.chars().collect();
+    cons(
+        chunks(nfa, &chars)
+            .into_iter()
+            .map(|chunk| chunk.iter().collect::<String>().into()),
+    )
+}
+
+/// Encode `input` for `Encoding::Chunked` in byte mode (see `Options::bytes`), where each chunk
+/// is an array of byte values, so `b"ab"` becomes `[[97, 98], []]` for the regex `ab`.
+pub fn encode_chunked_bytes(nfa: &Nfa, input: &[u8]) -> Value {
+    let chars: Vec<char> = input.iter().map(|&b| b as char).collect();
+    cons(chunks(nfa, &chars).into_iter().map(|chunk| {
+        chunk
+            .iter()
+            .map(|&c| Value::from(c as u32))
+            .collect::<Vec<_>>()
+            .into()
+    }))
+}
+
+/// Split `chars` into the chunks of `Encoding::Chunked`, following an accepting run of `nfa` if
+/// there is one.
+fn chunks<'a>(nfa: &Nfa, chars: &'a [char]) -> Vec<&'a [char]> {
+    let run = accepting_run(nfa, chars).unwrap_or_else(|| vec![nfa.start(); chars.len() + 1]);
+    let inside = nfa.literal_run_states();
+    let mut chunks = vec![];
+    let mut from = 0;
+    for to in 1..=chars.len() {
+        if !inside[run[to]] {
+            chunks.push(&chars[from..to]);
+            from = to;
+        }
+    }
+    chunks
+}
+
+/// Encode `input` for `Encoding::Tree`, using the states of an accepting run of `nfa` as the
+/// split states. If `nfa` doesn't accept `input`, the split states are arbitrary, and the result
+/// is rejected by the schema.
+///
+/// The encoding is `[end, tree]`, where `end` is the state the run ends in, and `tree` is `[]` for
+/// the empty input, the character itself for a single character, and otherwise `[split, left,
+/// right]`, where `left` encodes the first half of the input, `right` the second half, and `split`
+/// is the state between them.
+pub fn encode_tree(nfa: &Nfa, input: &str) -> Value {
+    let chars: Vec<char> = input.chars().collect();
+    tree_encoding(nfa, &chars, &char_value)
+}
+
+/// Encode `input` for `Encoding::Tree` in byte mode (see `Options::bytes`), where each byte is
+/// represented by its value.
+pub fn encode_tree_bytes(nfa: &Nfa, input: &[u8]) -> Value {
+    let chars: Vec<char> = input.iter().map(|&b| b as char).collect();
+    tree_encoding(nfa, &chars, &|c| (c as u32).into())
+}
+
+/// The tree encoding of `chars`, with each character represented by `leaf`.
+fn tree_encoding(nfa: &Nfa, chars: &[char], leaf: &dyn Fn(char) -> Value) -> Value {
+    let run = accepting_run(nfa, chars).unwrap_or_else(|| vec![nfa.start(); chars.len() + 1]);
+    let tree = if chars.is_empty() {
+        Vec::<()>::new().into()
+    } else {
+        tree(chars, &run, leaf, 0, chars.len())
+    };
+    vec![run[chars.len()].into(), tree].into()
+}
+
+/// The tree encoding of `chars[from..to]`, which must not be empty.
+fn tree(
+    chars: &[char],
+    run: &[State],
+    leaf: &dyn Fn(char) -> Value,
+    from: usize,
+    to: usize,
+) -> Value {
+    if to - from == 1 {
+        return leaf(chars[from]);
+    }
+    let mid = from + (to - from) / 2;
+    vec![
+        run[mid].into(),
+        tree(chars, run, leaf, from, mid),
+        tree(chars, run, leaf, mid, to),
+    ]
+    .into()
+}
+
+/// The states an accepting run of `nfa` on `chars` goes through, including the first and the last
+/// one, if `nfa` accepts `chars`.
+fn accepting_run(nfa: &Nfa, chars: &[char]) -> Option<Vec<State>> {
+    let run = longest_run(nfa, chars);
+    if run.len() == chars.len() + 1 && nfa.is_accepting(*run.last().unwrap()) {
+        Some(run)
+    } else {
+        None
+    }
+}
+
+/// The states a run of `nfa` on the longest prefix of `chars` it can consume goes through,
+/// including the first and the last one. If all of `chars` can be consumed, the run is an
+/// accepting one if possible.
+pub(crate) fn longest_run(nfa: &Nfa, chars: &[char]) -> Vec<State> {
+    let mut reachable = vec![BTreeSet::from([nfa.start()])];
+    for &c in chars {
+        let next: BTreeSet<State> = reachable
+            .last()
+            .unwrap()
+            .iter()
+            .flat_map(|&s| nfa.transitions(s))
+            .filter(|t| t.consumes(c))
+            .filter_map(|t| t.target())
+            .collect();
+        if next.is_empty() {
+            break;
+        }
+        reachable.push(next);
+    }
+
+    // Walk back from the last state, through states reachable from the start.
+    let last = reachable.last().unwrap();
+    let mut state = *last
+        .iter()
+        .find(|&&s| nfa.is_accepting(s))
+        .unwrap_or_else(|| last.iter().next().unwrap());
+    let mut run = vec![state];
+    for (i, &c) in chars[..reachable.len() - 1].iter().enumerate().rev() {
+        state = *reachable[i]
+            .iter()
+            .find(|&&s| {
+                nfa.transitions(s)
+                    .iter()
+                    .any(|t| t.consumes(c) && t.target() == Some(state))
+            })
+            .unwrap();
+        run.push(state);
+    }
+    run.reverse();
+    run
+}
+
+/// An encoded input which doesn't have the shape of its encoding.
+#[derive(Debug, PartialEq)]
+pub struct DecodeError {
+    /// A JSON Pointer to the offending value, in URI fragment form (`#` is the whole document).
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.path)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn malformed(path: &str, message: &str) -> DecodeError {
+    DecodeError {
+        path: path.to_string(),
+        message: message.to_string(),
+    }
+}
+
+/// The single character encoded by `value`.
+fn decode_char(value: &Value) -> Result<char, &'static str> {
+    let mut chars = value.as_str().ok_or("expected a string")?.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err("expected a single character"),
+    }
+}
+
+/// The byte encoded by `value`.
+fn decode_byte(value: &Value) -> Result<u8, &'static str> {
+    value
+        .as_u64()
+        .and_then(|b| u8::try_from(b).ok())
+        .ok_or("expected a byte value")
+}
+
+/// The characters of a chunk of `Encoding::Chunked`.
+fn decode_chunk(value: &Value) -> Result<Vec<char>, &'static str> {
+    match value.as_str() {
+        Some(chunk) if !chunk.is_empty() => Ok(chunk.chars().collect()),
+        _ => Err("expected a non-empty string"),
+    }
+}
+
+/// The bytes of a chunk of `Encoding::Chunked` in byte mode.
+fn decode_byte_chunk(value: &Value) -> Result<Vec<u8>, &'static str> {
+    match value.as_array() {
+        Some(chunk) if !chunk.is_empty() => chunk.iter().map(decode_byte).collect(),
+        _ => Err("expected a non-empty array of byte values"),
+    }
+}
+
+/// Recover the input from its encoding. The split states of `Encoding::Tree` are only checked to
+/// be state numbers, not to form a run of any automaton.
+pub fn decode_input(value: &Value, encoding: Encoding) -> Result<String, DecodeError> {
+    match encoding {
+        Encoding::Cons => decode_cons(value, &decode_char),
+        Encoding::Chunked => decode_cons(value, &decode_chunk).map(|chunks| chunks.concat()),
+        Encoding::Tree => decode_tree(value, &decode_char),
+    }
+    .map(|chars| chars.into_iter().collect())
+}
+
+/// Recover the input from its encoding in byte mode (see `Options::bytes`).
+pub fn decode_input_bytes(value: &Value, encoding: Encoding) -> Result<Vec<u8>, DecodeError> {
+    match encoding {
+        Encoding::Cons => decode_cons(value, &decode_byte),
+        Encoding::Chunked => decode_cons(value, &decode_byte_chunk).map(|chunks| chunks.concat()),
+        Encoding::Tree => decode_tree(value, &decode_byte),
+    }
+}
+
+/// A decoder of a single symbol of the input.
+type Leaf<'a, T> = &'a dyn Fn(&Value) -> Result<T, &'static str>;
+
+fn decode_cons<T>(mut value: &Value, leaf: Leaf<T>) -> Result<Vec<T>, DecodeError> {
+    let mut result = vec![];
+    // `value` is reached from the root by following the rest of the input once per character.
+    let path = |depth: usize| format!("#{}", "/1".repeat(depth));
+    for depth in 0.. {
+        match value.as_array().map(Vec::as_slice) {
+            Some([]) => break,
+            Some([c, rest]) => {
+                let c =
+                    leaf(c).map_err(|message| malformed(&format!("{}/0", path(depth)), message))?;
+                result.push(c);
+                value = rest;
+            }
+            _ => return Err(malformed(&path(depth), "expected `[]` or a pair")),
+        }
+    }
+    Ok(result)
+}
+
+fn decode_tree<T>(value: &Value, leaf: Leaf<T>) -> Result<Vec<T>, DecodeError> {
+    match value.as_array().map(Vec::as_slice) {
+        Some([end, tree]) => {
+            if !end.is_u64() {
+                return Err(malformed("#/0", "expected a state number"));
+            }
+            let mut result = vec![];
+            if *tree != Value::Array(vec![]) {
+                decode_subtree(tree, "#/1".to_string(), leaf, &mut result)?;
+            }
+            Ok(result)
+        }
+        _ => Err(malformed(
+            "#",
+            "expected a pair of a state number and a tree",
+        )),
+    }
+}
+
+fn decode_subtree<T>(
+    value: &Value,
+    path: String,
+    leaf: Leaf<T>,
+    result: &mut Vec<T>,
+) -> Result<(), DecodeError> {
+    match value {
+        Value::Array(items) => match items.as_slice() {
+            [split, left, right] => {
+                if !split.is_u64() {
+                    return Err(malformed(&format!("{}/0", path), "expected a state number"));
+                }
+                decode_subtree(left, format!("{}/1", path), leaf, result)?;
+                decode_subtree(right, format!("{}/2", path), leaf, result)
+            }
+            _ => Err(malformed(
+                &path,
+                "expected a character or a node of three items",
+            )),
+        },
+        _ => {
+            result.push(leaf(value).map_err(|message| malformed(&path, message))?);
+            Ok(())
+        }
+    }
+}
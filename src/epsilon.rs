@@ -0,0 +1,249 @@
+//! Removal of epsilon (`Goto`) transitions and assertions.
+
+use crate::error::Limit;
+use crate::nfa::GroupLabel;
+use crate::{Error, Look, Nfa, Options, State, Transition};
+use regex_syntax::hir::{Class, ClassUnicode, ClassUnicodeRange, HirKind};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// What kind of character is on one side of the current position, as far as assertions are
+/// concerned. `Edge` stands for the start of the input when looking back, and for its end when
+/// looking ahead.
+///
+/// Word characters are split into ASCII ones, which are word characters for both Unicode and ASCII
+/// word boundaries, and the remaining Unicode ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Context {
+    Edge,
+    Newline,
+    AsciiWord,
+    UnicodeWord,
+    Other,
+}
+
+/// A set of `Context`s, as a bit mask.
+type ContextSet = u8;
+
+const ALL_CONTEXTS: ContextSet = 0b11111;
+
+impl Context {
+    fn bit(self) -> ContextSet {
+        1 << self as u8
+    }
+
+    fn is_unicode_word(self) -> bool {
+        matches!(self, Context::AsciiWord | Context::UnicodeWord)
+    }
+
+    fn is_ascii_word(self) -> bool {
+        self == Context::AsciiWord
+    }
+}
+
+/// The context of the character `c`, or of the edge of the input if there is none.
+fn context(c: Option<char>) -> Context {
+    match c {
+        None => Context::Edge,
+        Some('\n') => Context::Newline,
+        Some(c) if c.is_ascii_alphanumeric() || c == '_' => Context::AsciiWord,
+        Some(c) if regex_syntax::is_word_character(c) => Context::UnicodeWord,
+        Some(_) => Context::Other,
+    }
+}
+
+/// Whether `look` holds between the characters `before` and `after`, where `None` stands for the
+/// edge of the input.
+pub(crate) fn look_holds(look: Look, before: Option<char>, after: Option<char>) -> bool {
+    allowed_after(look, context(before)) & context(after).bit() != 0
+}
+
+/// The contexts following the current position which satisfy `predicate`.
+fn contexts_where(predicate: impl Fn(Context) -> bool) -> ContextSet {
+    [
+        Context::Edge,
+        Context::Newline,
+        Context::AsciiWord,
+        Context::UnicodeWord,
+        Context::Other,
+    ]
+    .into_iter()
+    .filter(|&c| predicate(c))
+    .map(Context::bit)
+    .fold(0, |a, b| a | b)
+}
+
+/// The contexts following the current position in which `look` holds, given the context
+/// preceding it.
+fn allowed_after(look: Look, before: Context) -> ContextSet {
+    match look {
+        Look::StartText if before == Context::Edge => ALL_CONTEXTS,
+        Look::StartText => 0,
+        Look::EndText => Context::Edge.bit(),
+        Look::StartLine if matches!(before, Context::Edge | Context::Newline) => ALL_CONTEXTS,
+        Look::StartLine => 0,
+        Look::EndLine => Context::Edge.bit() | Context::Newline.bit(),
+        Look::WordBoundaryUnicode => {
+            contexts_where(|after| after.is_unicode_word() != before.is_unicode_word())
+        }
+        Look::NotWordBoundaryUnicode => {
+            contexts_where(|after| after.is_unicode_word() == before.is_unicode_word())
+        }
+        Look::WordBoundaryAscii => {
+            contexts_where(|after| after.is_ascii_word() != before.is_ascii_word())
+        }
+        Look::NotWordBoundaryAscii => {
+            contexts_where(|after| after.is_ascii_word() == before.is_ascii_word())
+        }
+    }
+}
+
+/// Split all characters into classes by their `Context`. Only the distinctions needed by the given
+/// assertions are made, the rest of the characters fall into `Context::Other`.
+fn context_classes(looks: &[Look]) -> Vec<(Context, ClassUnicode)> {
+    let mut classes = vec![(
+        Context::Newline,
+        ClassUnicode::new([ClassUnicodeRange::new('\n', '\n')]),
+    )];
+    let uses = |kinds: &[Look]| looks.iter().any(|look| kinds.contains(look));
+    let unicode_words = uses(&[Look::WordBoundaryUnicode, Look::NotWordBoundaryUnicode]);
+    let ascii_words = unicode_words || uses(&[Look::WordBoundaryAscii, Look::NotWordBoundaryAscii]);
+    let ascii_word = ClassUnicode::new([
+        ClassUnicodeRange::new('0', '9'),
+        ClassUnicodeRange::new('A', 'Z'),
+        ClassUnicodeRange::new('_', '_'),
+        ClassUnicodeRange::new('a', 'z'),
+    ]);
+    if unicode_words {
+        let mut unicode_word = match regex_syntax::Parser::new()
+            .parse(r"\w")
+            .unwrap()
+            .into_kind()
+        {
+            HirKind::Class(Class::Unicode(class)) => class,
+            _ => unreachable!(),
+        };
+        unicode_word.difference(&ascii_word);
+        classes.push((Context::UnicodeWord, unicode_word));
+    }
+    if ascii_words {
+        classes.push((Context::AsciiWord, ascii_word));
+    }
+    let mut other = ClassUnicode::empty();
+    for (_, class) in &classes {
+        other.union(class);
+    }
+    other.negate();
+    classes.push((Context::Other, other));
+    classes
+}
+
+/// Convert `nfa` to an equivalent automaton without `Goto` and `Assert` transitions.
+///
+/// Each state gets the consuming transitions of all states in its epsilon-closure, and accepts if
+/// any of them accepts. To resolve assertions, states of the result are pairs of an NFA state and
+/// the context of the previous character; an assertion then restricts which characters may be
+/// consumed next. If the NFA has no assertions, the context is not tracked at all.
+///
+/// Only states reachable from the start state are kept, numbered in breadth-first order. The
+/// result is checked against the size limits in `options` as it grows.
+pub fn remove_epsilons(nfa: &Nfa, options: &Options) -> Result<Nfa, Error> {
+    let looks: Vec<Look> = (0..nfa.num_states())
+        .flat_map(|s| nfa.transitions(s))
+        .filter_map(|t| match t {
+            Transition::Assert(look, _) => Some(*look),
+            _ => None,
+        })
+        .collect();
+    let (start_context, context_classes) = if !looks.is_empty() {
+        (Context::Edge, context_classes(&looks))
+    } else {
+        let any = ClassUnicode::new([ClassUnicodeRange::new('\0', char::MAX)]);
+        (Context::Other, vec![(Context::Other, any)])
+    };
+
+    let mut result = Nfa::default();
+    let mut mapping = HashMap::new();
+    let mut queue = VecDeque::new();
+    let result_start = result.new_state();
+    mapping.insert((nfa.start(), start_context), result_start);
+    queue.push_back((nfa.start(), start_context));
+
+    while let Some((state, before)) = queue.pop_front() {
+        let from = mapping[&(state, before)];
+        let mut transitions: Vec<(Transition, &GroupLabel)> = vec![];
+        for (s, after) in closure(nfa, state, before) {
+            for (i, t) in nfa.transitions(s).iter().enumerate() {
+                match *t {
+                    Transition::Goto(_) | Transition::Assert(..) => {}
+                    Transition::Accept => {
+                        if after & Context::Edge.bit() != 0 {
+                            transitions.push((Transition::Accept, &GroupLabel::Unlabelled));
+                        }
+                    }
+                    Transition::Consume(_, target) | Transition::ConsumeClass(_, target) => {
+                        for (context, chars) in &context_classes {
+                            if after & context.bit() == 0 {
+                                continue;
+                            }
+                            let mut class = t.unicode_class().unwrap();
+                            class.intersect(chars);
+                            if class.ranges().is_empty() {
+                                continue;
+                            }
+                            let to = *mapping.entry((target, *context)).or_insert_with(|| {
+                                queue.push_back((target, *context));
+                                result.new_state()
+                            });
+                            let label = nfa.transition_group_label(s, i);
+                            transitions.push((Transition::consume(class, to), label));
+                        }
+                    }
+                }
+            }
+        }
+        let mut unique: Vec<(Transition, GroupLabel)> = vec![];
+        for (t, label) in transitions {
+            match unique.iter_mut().find(|(u, _)| *u == t) {
+                Some((_, existing)) => existing.merge(label),
+                None => unique.push((t, label.clone())),
+            }
+        }
+        // Keep `Accept` last, where the NFA builder puts it.
+        unique.sort_by_key(|(t, _)| matches!(t, Transition::Accept));
+        for (t, label) in unique {
+            result.add_labelled_transition(from, t, label);
+        }
+        result.check_size(Limit::NfaStates, options)?;
+    }
+
+    Ok(result)
+}
+
+/// The states reachable from `state` by `Goto` and `Assert` transitions alone, given the context
+/// preceding the current position. Each state is paired with the set of contexts following the
+/// current position for which it is reachable.
+fn closure(nfa: &Nfa, state: State, before: Context) -> BTreeMap<State, ContextSet> {
+    let mut result = BTreeMap::new();
+    let mut stack = vec![(state, ALL_CONTEXTS)];
+    while let Some((state, after)) = stack.pop() {
+        let known = result.entry(state).or_insert(0);
+        let new = after & !*known;
+        if new == 0 {
+            continue;
+        }
+        *known |= new;
+        for t in nfa.transitions(state) {
+            match *t {
+                Transition::Goto(target) => stack.push((target, new)),
+                Transition::Assert(look, target) => {
+                    let allowed = new & allowed_after(look, before);
+                    if allowed != 0 {
+                        stack.push((target, allowed));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+use std::ops::Range;
+
+/// A range of byte offsets into the regex pattern.
+pub type Span = Range<usize>;
+
+/// An error converting a regex.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The pattern is not a valid regex.
+    Parse { message: String, span: Span },
+
+    /// The regex uses a construct which can't be converted. `construct` is the offending
+    /// literal, class or assertion, as printed by `regex_syntax`, and `span` its position in the
+    /// pattern, if known.
+    Unsupported {
+        construct: String,
+        span: Option<Span>,
+    },
+
+    /// A counted repetition expanded to more NFA states than `Options::max_repetition_states`.
+    RepetitionTooLarge { limit: usize, span: Span },
+
+    /// The automaton or the schema exceeded one of the size limits in `Options`.
+    TooLarge { what: Limit, limit: usize },
+
+    /// Another field given to `compile_fields` has the same name.
+    DuplicateField,
+}
+
+/// A size limit in `Options`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    /// `Options::max_nfa_states`
+    NfaStates,
+    /// `Options::max_dfa_states`
+    DfaStates,
+    /// `Options::max_transitions`
+    Transitions,
+    /// `Options::max_schema_bytes`
+    SchemaBytes,
+}
+
+impl Error {
+    /// The part of the pattern the error is about, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Error::Parse { span, .. } | Error::RepetitionTooLarge { span, .. } => {
+                Some(span.clone())
+            }
+            Error::Unsupported { span, .. } => span.clone(),
+            Error::TooLarge { .. } | Error::DuplicateField => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse { message, .. } => write!(f, "{}", message),
+            Error::Unsupported { construct, .. } => {
+                write!(f, "unsupported construct: {}", construct)
+            }
+            Error::RepetitionTooLarge { limit, .. } => write!(
+                f,
+                "counted repetition expands to more than {} NFA states",
+                limit
+            ),
+            Error::TooLarge { what, limit } => match what {
+                Limit::NfaStates => write!(f, "the NFA has more than {} states", limit),
+                Limit::DfaStates => write!(f, "the DFA has more than {} states", limit),
+                Limit::Transitions => {
+                    write!(f, "the automaton has more than {} transitions", limit)
+                }
+                Limit::SchemaBytes => write!(f, "the schema is larger than {} bytes", limit),
+            },
+            Error::DuplicateField => write!(f, "duplicate field name"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<regex_syntax::ast::Error> for Error {
+    fn from(error: regex_syntax::ast::Error) -> Self {
+        Error::Parse {
+            message: error.kind().to_string(),
+            span: error.span().start.offset..error.span().end.offset,
+        }
+    }
+}
+
+impl From<regex_syntax::hir::Error> for Error {
+    fn from(error: regex_syntax::hir::Error) -> Self {
+        Error::Parse {
+            message: error.kind().to_string(),
+            span: error.span().start.offset..error.span().end.offset,
+        }
+    }
+}
+
+/// An error converting the regex of one of several fields.
+#[derive(Debug)]
+pub struct FieldError {
+    pub field: String,
+    pub error: Error,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "in field {}: {}", self.field, self.error)
+    }
+}
+
+impl std::error::Error for FieldError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
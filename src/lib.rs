@@ -0,0 +1,1429 @@
+//! Convert regular expressions to JSON Schemas.
+//!
+//! JSON Schema has no way to express arbitrary regular languages over strings, so the schemas
+//! generated here validate an encoding of the string instead (see `Encoding`). By default each
+//! character is paired with the rest of the input: `"ab"` is encoded as `["a", ["b", []]]`.
+//!
+//! ```
+//! use regex_to_json_schema::{compile, encode_input, Options};
+//!
+//! let schema = compile("a+b", &Options::default()).unwrap();
+//! let input = encode_input("aab");
+//! # let _ = (schema, input);
+//! ```
+
+use regex_syntax::ast::Ast;
+use regex_syntax::hir::{ClassUnicode, ClassUnicodeRange, Hir};
+use schemars::schema::RootSchema;
+use serde_json::Value;
+use std::collections::HashSet;
+
+mod capture;
+mod combine;
+mod dfa;
+mod dot;
+mod encoding;
+mod epsilon;
+mod error;
+mod matching;
+mod nfa;
+mod pattern;
+mod schema;
+
+pub use combine::combine_schemas;
+pub use dot::{write_dot, write_dot_with_path};
+pub use encoding::{
+    decode_input, decode_input_bytes, encode_chunked, encode_chunked_bytes, encode_input,
+    encode_input_bytes, encode_tree, encode_tree_bytes, DecodeError, Encoder,
+};
+pub use error::{Error, FieldError, Limit, Span};
+pub use matching::{Capture, CaptureMatcher, Captures};
+pub use nfa::{CharClass, Look, Nfa, State, Transition};
+pub use pattern::{code_point_pattern, EcmaPattern};
+pub use schema::{pattern_schema, to_json_schema};
+
+/// Options controlling how a regex is converted.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Options {
+    /// Match letters case-insensitively, as with the `i` flag.
+    pub case_insensitive: bool,
+
+    /// Make `^` and `$` match at the beginning and end of lines, as with the `m` flag.
+    pub multi_line: bool,
+
+    /// Make `.` match `\n`, as with the `s` flag.
+    pub dot_matches_new_line: bool,
+
+    /// Interpret the regex as Unicode rather than ASCII, as with the `u` flag.
+    pub unicode: bool,
+
+    /// Accept regexes which can match invalid UTF-8, such as `(?-u:\xFF)`. Only their ASCII parts
+    /// can be converted to schemas.
+    pub allow_invalid_utf8: bool,
+
+    /// Maximum nesting depth of the regex.
+    pub nest_limit: u32,
+
+    /// Match bytes rather than characters: the automaton consumes the UTF-8 encoding of the input,
+    /// and inputs are encoded as arrays of byte values (see `encode_input_bytes`). Implies
+    /// `allow_invalid_utf8`, and all of the regex can be converted except Unicode word boundaries
+    /// (`\b` and `\B` with the `u` flag, which is on by default), which would have to decode the
+    /// characters around them; use `(?-u:\b)` for ASCII word boundaries instead.
+    pub bytes: bool,
+
+    /// Maximum number of NFA states a single counted repetition (`{n}`, `{n,}`, `{n,m}`) may
+    /// expand to.
+    pub max_repetition_states: usize,
+
+    /// Maximum number of states of the NFA, both as built from the regex and after removing
+    /// epsilon transitions.
+    pub max_nfa_states: usize,
+
+    /// Maximum number of states of the DFA, including the intermediate one built by `minimize`.
+    pub max_dfa_states: usize,
+
+    /// Maximum number of transitions of any of the automata.
+    pub max_transitions: usize,
+
+    /// Maximum size of the schema produced by `compile`, serialized without whitespace.
+    pub max_schema_bytes: usize,
+
+    /// Convert the NFA to a DFA before output.
+    pub determinize: bool,
+
+    /// Convert the NFA to the minimal DFA before output. Implies `determinize`.
+    pub minimize: bool,
+
+    /// Whether the regex has to match the whole input, or just some part of it.
+    pub match_mode: MatchMode,
+
+    /// How inputs are encoded as JSON documents.
+    pub encoding: Encoding,
+
+    /// Which JSON Schema draft to generate schemas for.
+    pub draft: Draft,
+
+    /// Prefix the names of definitions with the name of the capture group whose characters lead
+    /// into their states (see `Nfa::group_name`), as in `year_S3` for `(?P<year>\d{4})`.
+    pub group_names: bool,
+
+    /// Describe the named capture groups in the schema. Each group gets a definition
+    /// `capture.{name}`, accepting the encodings of the strings the group matches on its own. In
+    /// `Encoding::Cons` and `Encoding::Chunked`, the steps of the encoded input which leave or
+    /// enter groups are annotated with an `x-capture` keyword, such as
+    /// `{"leave": ["month"], "enter": ["day"]}`, listing the groups from the innermost to the
+    /// outermost and from the outermost to the innermost, respectively. Annotations are left out
+    /// where the automaton merges paths in different groups, as minimizing it may do.
+    pub capture_groups: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            case_insensitive: false,
+            multi_line: false,
+            dot_matches_new_line: false,
+            unicode: true,
+            allow_invalid_utf8: false,
+            nest_limit: 250,
+            bytes: false,
+            max_repetition_states: 10_000,
+            max_nfa_states: 100_000,
+            max_dfa_states: 10_000,
+            max_transitions: 1_000_000,
+            max_schema_bytes: 64 << 20,
+            determinize: false,
+            minimize: false,
+            match_mode: MatchMode::Full,
+            encoding: Encoding::Cons,
+            draft: Draft::Draft7,
+            group_names: false,
+            capture_groups: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ArgEnum))]
+pub enum MatchMode {
+    /// The whole input has to match, as if the regex was wrapped in `\A(?:...)\z`.
+    Full,
+    /// Some part of the input has to match, as if the regex was wrapped in
+    /// `(?s:.)*(?:...)(?s:.)*`.
+    Search,
+}
+
+/// How an input string is represented as a JSON document.
+///
+/// JSON Schema can't relate an array item to its neighbours, so a flat array of characters can't
+/// be validated against an automaton; all three encodings nest instead, `Cons` and `Chunked` a
+/// level per step of the automaton, and `Tree` a level per halving of the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ArgEnum))]
+pub enum Encoding {
+    /// Each character is paired with the encoding of the rest of the input, so the document nests
+    /// as deeply as the input is long (see `encode_input`).
+    Cons,
+    /// Like `Cons`, but each step consumes a chunk of the input rather than a single character:
+    /// runs of literal characters which the automaton can only consume one after another, such as
+    /// `https://` in `https?://`, are consumed as one string. Encoding requires the regex (see
+    /// `encode_chunked`).
+    Chunked,
+    /// A balanced binary tree of the characters, annotated with automaton states, so the document
+    /// nests logarithmically deep. Encoding requires the regex (see `encode_tree`).
+    Tree,
+}
+
+/// A JSON Schema draft.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ArgEnum))]
+pub enum Draft {
+    #[cfg_attr(feature = "cli", clap(name = "07"))]
+    Draft7,
+    #[cfg_attr(feature = "cli", clap(name = "2019-09"))]
+    Draft201909,
+    #[cfg_attr(feature = "cli", clap(name = "2020-12"))]
+    Draft202012,
+}
+
+/// Convert `regex` to a JSON Schema accepting the encodings (see `Encoding`) of exactly the
+/// strings it matches.
+///
+/// Characters are validated with `enum`s and ECMA-262 patterns which work with and without the `u`
+/// flag, except for classes with more than 1024 astral characters, but not all of them, such as
+/// Unicode `\w` or `\pL`: their patterns contain ranges of astral characters, which validators
+/// only accept with the `u` flag or if they match code points, as most do.
+pub fn compile(regex: &str, options: &Options) -> Result<RootSchema, Error> {
+    let (ast, hir) = parse(regex, options)?;
+    let builder = nfa::Builder::new(options, &ast, &hir);
+    let nfa = build_nfa(&builder, &hir, options)?;
+    if options.encoding == Encoding::Tree {
+        schema::check_tree_size(&nfa, options)?;
+    }
+    let mut schema = to_json_schema(&nfa, options);
+    if options.capture_groups {
+        schema = capture::add_group_definitions(schema, &builder, &hir, options)?;
+    }
+    schema::check_size(&schema, options)?;
+    Ok(schema)
+}
+
+/// Convert the regex of each field, given as `(name, regex)`, and combine the schemas into one
+/// accepting objects with each field's encoded input under its name (see `combine_schemas`). The
+/// size limits in `options` apply to each field separately. Fields must have distinct names;
+/// otherwise, the error for the second one is `Error::DuplicateField`.
+pub fn compile_fields(
+    fields: impl IntoIterator<Item = (impl Into<String>, impl AsRef<str>)>,
+    options: &Options,
+) -> Result<RootSchema, FieldError> {
+    let mut names = HashSet::new();
+    let schemas = fields
+        .into_iter()
+        .map(|(field, regex)| {
+            let field = field.into();
+            if !names.insert(field.clone()) {
+                let error = Error::DuplicateField;
+                return Err(FieldError { field, error });
+            }
+            match compile(regex.as_ref(), options) {
+                Ok(schema) => Ok((field, schema)),
+                Err(error) => Err(FieldError { field, error }),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(combine_schemas(schemas, options.draft))
+}
+
+/// Encode `input` as a JSON document suitable for validating against the schema generated by
+/// `compile` with the same `regex` and `options`. With `options.bytes`, the UTF-8 encoding of
+/// `input` is encoded. To encode several inputs, use an `Encoder`, which compiles `regex` once.
+pub fn encode(regex: &str, input: &str, options: &Options) -> Result<Value, Error> {
+    Ok(Encoder::new(regex, options)?.encode(input))
+}
+
+/// Translate `regex` to an ECMA-262 regex which can be used as a JSON Schema `pattern` (see
+/// `pattern_schema`) to validate plain strings, matching them as `regex` does under
+/// `options.match_mode` and the flags in `options`.
+pub fn ecma_pattern(regex: &str, options: &Options) -> Result<EcmaPattern, Error> {
+    let (ast, hir) = parse(regex, options)?;
+    let builder = nfa::Builder::new(options, &ast, &hir);
+    Ok(pattern::to_ecma_pattern(
+        regex,
+        &hir,
+        &builder,
+        options.match_mode,
+    ))
+}
+
+/// Convert `regex` to an automaton without `Goto` or `Assert` transitions, from which the schema
+/// is generated. The states are numbered in breadth-first order from the start state, visiting
+/// the transitions out of each state in the order of the characters they consume, so that small
+/// changes to the regex don't renumber unrelated states.
+pub fn generate_nfa(regex: &str, options: &Options) -> Result<Nfa, Error> {
+    let (ast, hir) = parse(regex, options)?;
+    let builder = nfa::Builder::new(options, &ast, &hir);
+    build_nfa(&builder, &hir, options)
+}
+
+/// Convert `hir`, which must be part of the regex `builder` was created for, to an automaton as
+/// described in `generate_nfa`.
+fn build_nfa(builder: &nfa::Builder, hir: &Hir, options: &Options) -> Result<Nfa, Error> {
+    let mut nfa = Nfa::default();
+    let start = nfa.new_state();
+    let end = nfa.new_state();
+    match options.match_mode {
+        MatchMode::Full => builder.regex_to_nfa(&mut nfa, hir, start, end)?,
+        MatchMode::Search => {
+            // Surround the regex with loops consuming anything, through fresh states so that the
+            // loops can't be entered from inside the regex.
+            let max = if options.bytes { '\u{ff}' } else { char::MAX };
+            let any = ClassUnicode::new([ClassUnicodeRange::new('\0', max)]);
+            let regex_start = nfa.new_state();
+            let regex_end = nfa.new_state();
+            builder.add_consume(&mut nfa, start, Transition::consume(any.clone(), start));
+            nfa.add_transition(start, Transition::Goto(regex_start));
+            builder.regex_to_nfa(&mut nfa, hir, regex_start, regex_end)?;
+            nfa.add_transition(regex_end, Transition::Goto(end));
+            builder.add_consume(&mut nfa, end, Transition::consume(any, end));
+        }
+    }
+    nfa.add_transition(end, Transition::Accept);
+    let nfa = epsilon::remove_epsilons(&nfa, options)?;
+    let nfa = if options.minimize {
+        dfa::minimize(&nfa, options)?
+    } else if options.determinize {
+        dfa::determinize(&nfa, options)?
+    } else {
+        nfa
+    };
+    Ok(nfa.renumbered())
+}
+
+fn parse(regex: &str, options: &Options) -> Result<(Ast, Hir), Error> {
+    let ast = regex_syntax::ast::parse::ParserBuilder::new()
+        .nest_limit(options.nest_limit)
+        .build()
+        .parse(regex)?;
+    let hir = regex_syntax::hir::translate::TranslatorBuilder::new()
+        .case_insensitive(options.case_insensitive)
+        .multi_line(options.multi_line)
+        .dot_matches_new_line(options.dot_matches_new_line)
+        .unicode(options.unicode)
+        .allow_invalid_utf8(options.allow_invalid_utf8 || options.bytes)
+        .build()
+        .translate(regex, &ast)?;
+    Ok((ast, hir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// All strings over `alphabet` of length at most `max_len`.
+    fn inputs(alphabet: &str, max_len: usize) -> Vec<String> {
+        let mut result = vec![String::new()];
+        let mut last = vec![String::new()];
+        for _ in 0..max_len {
+            last = last
+                .iter()
+                .flat_map(|s| alphabet.chars().map(move |c| format!("{}{}", s, c)))
+                .collect();
+            result.extend(last.iter().cloned());
+        }
+        result
+    }
+
+    /// `regex` compiled by the regex crate, matching as it should under `options`.
+    fn regex_crate(regex: &str, options: &Options) -> regex::Regex {
+        let regex = match options.match_mode {
+            MatchMode::Full => format!(r"\A(?:{})\z", regex),
+            MatchMode::Search => regex.to_string(),
+        };
+        regex::RegexBuilder::new(&regex)
+            .case_insensitive(options.case_insensitive)
+            .multi_line(options.multi_line)
+            .dot_matches_new_line(options.dot_matches_new_line)
+            .unicode(options.unicode)
+            .build()
+            .unwrap()
+    }
+
+    /// Check that the schema accepts exactly the inputs (over `alphabet`) which the regex crate
+    /// matches, in all output modes and encodings.
+    fn assert_agrees_with_regex(regex: &str, alphabet: &str, match_mode: MatchMode) {
+        for encoding in [Encoding::Cons, Encoding::Chunked, Encoding::Tree] {
+            for options in [
+                Options {
+                    match_mode,
+                    encoding,
+                    ..Options::default()
+                },
+                Options {
+                    match_mode,
+                    encoding,
+                    determinize: true,
+                    ..Options::default()
+                },
+                Options {
+                    match_mode,
+                    encoding,
+                    minimize: true,
+                    ..Options::default()
+                },
+            ] {
+                assert_schema_agrees_with_regex(regex, alphabet, &options);
+            }
+        }
+    }
+
+    fn assert_schema_agrees_with_regex(regex: &str, alphabet: &str, options: &Options) {
+        let nfa = generate_nfa(regex, options).unwrap();
+        assert!((0..nfa.num_states())
+            .flat_map(|s| nfa.transitions(s))
+            .all(|t| !matches!(t, Transition::Goto(_))));
+        let schema = serde_json::to_value(to_json_schema(&nfa, options)).unwrap();
+        let validator = jsonschema::JSONSchema::compile(&schema).unwrap();
+        let expected = regex_crate(regex, options);
+        let encoder = Encoder::new(regex, options).unwrap();
+        for input in inputs(alphabet, 4) {
+            let encoded = encoder.encode(&input);
+            assert_eq!(
+                validator.is_valid(&encoded),
+                expected.is_match(&input),
+                "regex {:?}, input {:?}, options: {:?}",
+                regex,
+                input,
+                options
+            );
+        }
+    }
+
+    #[test]
+    fn ambiguous_regexes_match_like_regex_crate() {
+        for regex in [
+            "a|a",
+            "(a|ab)(c|bc)",
+            "a*a*",
+            "(a|b)*abb",
+            "a{1,3}a{2}",
+            "[ab]|a",
+            "a|b*",
+            "(ab|a)+b?",
+            "(a*)*",
+            "(a?)+",
+            "()*",
+            "(a|b?)*c",
+            "(a*b*)*c?",
+        ] {
+            assert_agrees_with_regex(regex, "abc", MatchMode::Full);
+        }
+    }
+
+    #[test]
+    fn counted_repetitions_match_like_regex_crate() {
+        for regex in [
+            "a{3}",
+            "a{0}b",
+            "a{2,}",
+            "a{0,2}b",
+            "(ab){1,2}",
+            "(a|b){2,3}b",
+            "(a{2}){2}",
+            "a{1,2}?b{1,}?",
+            "(a?){2,}",
+        ] {
+            assert_agrees_with_regex(regex, "ab", MatchMode::Full);
+        }
+    }
+
+    #[test]
+    fn counted_repetitions_are_capped() {
+        let options = Options {
+            max_repetition_states: 9,
+            ..Options::default()
+        };
+        // `a{10}` chains ten copies of `a` through nine new states.
+        assert!(compile("a{10}", &options).is_ok());
+        // The cap applies to each repetition separately.
+        assert!(compile("a{10}b{10}", &options).is_ok());
+        for (regex, span) in [("a{11}", 0..5), ("ba{1,11}", 1..8), ("(ab){5,}", 0..8)] {
+            let error = compile(regex, &options).unwrap_err();
+            assert!(
+                matches!(error, Error::RepetitionTooLarge { limit: 9, .. }),
+                "{}: {:?}",
+                regex,
+                error
+            );
+            assert_eq!(error.span(), Some(span), "{}", regex);
+        }
+    }
+
+    #[test]
+    fn every_definition_consumes_or_ends_the_input() {
+        /// Whether `schema` accepts only a step consuming part of the input, or its end.
+        fn consumes(schema: &Value) -> bool {
+            match ["oneOf", "anyOf"].iter().find_map(|k| schema.get(k)) {
+                Some(branches) => branches.as_array().unwrap().iter().all(consumes),
+                None => schema["const"] == serde_json::json!([]) || schema["type"] == "array",
+            }
+        }
+
+        for regex in ["(a*)*", "(a?)+", "()*", "(a|b?)*c", "(a*b*)*c?", "(^|a)+$"] {
+            for encoding in [Encoding::Cons, Encoding::Chunked] {
+                let options = Options {
+                    encoding,
+                    ..Options::default()
+                };
+                let nfa = generate_nfa(regex, &options).unwrap();
+                for state in 0..nfa.num_states() {
+                    for t in nfa.transitions(state) {
+                        assert!(
+                            !matches!(t, Transition::Goto(_) | Transition::Assert(..)),
+                            "{}: {:?}",
+                            regex,
+                            t
+                        );
+                    }
+                }
+                let schema = serde_json::to_value(to_json_schema(&nfa, &options)).unwrap();
+                for (name, definition) in schema["definitions"].as_object().unwrap() {
+                    assert!(consumes(definition), "{} in {}: {}", name, regex, schema);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn anchors_match_like_regex_crate() {
+        for regex in [
+            "^a",
+            "a$",
+            "^a|b$",
+            "a^b",
+            r"\Aa*\z",
+            "(?m)^a$",
+            "(?m)a$\n^b",
+            "(?m)(^|a)+$",
+            "(?m)$^",
+            "",
+            "ab|ba",
+        ] {
+            assert_agrees_with_regex(regex, "ab\n", MatchMode::Full);
+            assert_agrees_with_regex(regex, "ab\n", MatchMode::Search);
+        }
+    }
+
+    #[test]
+    fn word_boundaries_match_like_regex_crate() {
+        for regex in [
+            r"\ba",
+            r"a\b",
+            r"\Ba",
+            r"\b",
+            r"\B",
+            r"\ba+\b",
+            r"(?-u:\b)a",
+            r"a(?-u:\b)",
+            r"\bé\b",
+            r"(?m)^\b",
+        ] {
+            assert_agrees_with_regex(regex, "aé \n", MatchMode::Full);
+            assert_agrees_with_regex(regex, "aé \n", MatchMode::Search);
+        }
+    }
+
+    #[test]
+    fn schemas_use_the_keywords_of_each_draft() {
+        /// Whether `keyword` is used anywhere in `schema` as a keyword, that is, not as a
+        /// definition name or a value.
+        fn uses_keyword(schema: &Value, keyword: &str) -> bool {
+            match schema {
+                Value::Object(object) => object.iter().any(|(key, value)| {
+                    key == keyword
+                        || match key.as_str() {
+                            "const" | "enum" => false,
+                            "definitions" | "$defs" => value
+                                .as_object()
+                                .unwrap()
+                                .values()
+                                .any(|v| uses_keyword(v, keyword)),
+                            _ => uses_keyword(value, keyword),
+                        }
+                }),
+                Value::Array(items) => items.iter().any(|v| uses_keyword(v, keyword)),
+                _ => false,
+            }
+        }
+
+        let regex = regex::Regex::new(r"\A(?:(a|bc)*)\z").unwrap();
+        for (draft, jsonschema_draft, obsolete) in [
+            (
+                Draft::Draft7,
+                jsonschema::Draft::Draft7,
+                &["$defs", "prefixItems"][..],
+            ),
+            (
+                Draft::Draft201909,
+                jsonschema::Draft::Draft201909,
+                &["definitions", "prefixItems"],
+            ),
+            (
+                Draft::Draft202012,
+                jsonschema::Draft::Draft202012,
+                &["definitions", "additionalItems"],
+            ),
+        ] {
+            for encoding in [Encoding::Cons, Encoding::Chunked, Encoding::Tree] {
+                let options = Options {
+                    draft,
+                    encoding,
+                    ..Options::default()
+                };
+                let schema = serde_json::to_value(compile("(a|bc)*", &options).unwrap()).unwrap();
+                assert_eq!(schema["$schema"], draft.meta_schema());
+                for keyword in obsolete {
+                    assert!(!uses_keyword(&schema, keyword), "{} in {}", keyword, schema);
+                }
+                // Compiling also checks the schema against the draft's meta-schema.
+                let validator = jsonschema::JSONSchema::options()
+                    .with_draft(jsonschema_draft)
+                    .compile(&schema)
+                    .unwrap();
+                let encoder = Encoder::new("(a|bc)*", &options).unwrap();
+                for input in inputs("abc", 4) {
+                    let encoded = encoder.encode(&input);
+                    assert_eq!(
+                        validator.is_valid(&encoded),
+                        regex.is_match(&input),
+                        "input {:?}, options {:?}",
+                        input,
+                        options
+                    );
+                }
+            }
+            let schema = serde_json::to_value(pattern_schema("^a$", draft)).unwrap();
+            assert_eq!(schema["$schema"], draft.meta_schema());
+            jsonschema::JSONSchema::options()
+                .with_draft(jsonschema_draft)
+                .compile(&schema)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn flags_match_like_regex_crate() {
+        for regex in ["a.b", "^k$", "(?-i:a)|B", r"[a-c]+\w", "(?s-m:.$)"] {
+            for (case_insensitive, multi_line, dot_matches_new_line, unicode) in [
+                (true, false, false, true),
+                (false, true, false, true),
+                (false, false, true, true),
+                (true, true, true, false),
+            ] {
+                // Without Unicode, `.` matches any byte, which can be invalid UTF-8.
+                if !unicode && regex.contains('.') {
+                    continue;
+                }
+                for minimize in [false, true] {
+                    let options = Options {
+                        case_insensitive,
+                        multi_line,
+                        dot_matches_new_line,
+                        unicode,
+                        minimize,
+                        ..Options::default()
+                    };
+                    assert_schema_agrees_with_regex(regex, "aAbBk\u{212a}\né", &options);
+                }
+            }
+        }
+
+        // Case-insensitive literals become classes rather than alternations.
+        let options = Options {
+            case_insensitive: true,
+            ..Options::default()
+        };
+        let schema = serde_json::to_value(compile("k", &options).unwrap()).unwrap();
+        assert_eq!(
+            schema["definitions"]["S0"]["items"][0],
+            serde_json::json!({ "enum": ["K", "k", "\u{212a}"] })
+        );
+
+        let options = Options {
+            allow_invalid_utf8: true,
+            ..Options::default()
+        };
+        assert!(matches!(
+            compile(r"(?-u:\xFF)", &Options::default()),
+            Err(Error::Parse { .. })
+        ));
+        assert!(matches!(
+            compile(r"(?-u:\xFF)", &options),
+            Err(Error::Unsupported { .. })
+        ));
+
+        let options = Options {
+            nest_limit: 1,
+            ..Options::default()
+        };
+        assert!(compile("(a)", &options).is_ok());
+        assert!(matches!(
+            compile("((a))", &options),
+            Err(Error::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn byte_mode_matches_like_regex_crate() {
+        // All byte strings of length at most 3 over a few bytes, including parts of the UTF-8
+        // encodings of `é` (C3 A9) and `ë` (C3 AB).
+        let mut inputs = vec![vec![]];
+        for len in 1..=3 {
+            let previous: Vec<Vec<u8>> = inputs
+                .iter()
+                .filter(|i| i.len() == len - 1)
+                .cloned()
+                .collect();
+            for input in previous {
+                for b in [b'a', b' ', 0xc3, 0xa9, 0xab, 0xe9] {
+                    let mut next = input.clone();
+                    next.push(b);
+                    inputs.push(next);
+                }
+            }
+        }
+        for regex in [
+            "é",
+            "(?-u:\\xe9)",
+            "[é-ë]+",
+            "(?-u:[\\x80-\\xff])a?",
+            ".|a",
+            r"(?-u:\b)a",
+            "(?i)É",
+        ] {
+            for match_mode in [MatchMode::Full, MatchMode::Search] {
+                for encoding in [Encoding::Cons, Encoding::Chunked, Encoding::Tree] {
+                    for minimize in [false, true] {
+                        let options = Options {
+                            bytes: true,
+                            match_mode,
+                            encoding,
+                            minimize,
+                            ..Options::default()
+                        };
+                        let nfa = generate_nfa(regex, &options).unwrap();
+                        let schema = serde_json::to_value(to_json_schema(&nfa, &options)).unwrap();
+                        let validator = jsonschema::JSONSchema::compile(&schema).unwrap();
+                        let expected = regex::bytes::Regex::new(&match match_mode {
+                            MatchMode::Full => format!(r"\A(?:{})\z", regex),
+                            MatchMode::Search => regex.to_string(),
+                        })
+                        .unwrap();
+                        let encoder = Encoder::new(regex, &options).unwrap();
+                        for input in &inputs {
+                            let encoded = encoder.encode_bytes(input);
+                            assert_eq!(decode_input_bytes(&encoded, encoding).as_ref(), Ok(input));
+                            assert_eq!(
+                                validator.is_valid(&encoded),
+                                expected.is_match(input),
+                                "regex {:?}, input {:?}, options {:?}",
+                                regex,
+                                input,
+                                options
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        let options = Options {
+            bytes: true,
+            ..Options::default()
+        };
+        assert!(matches!(
+            compile(r"\b", &options),
+            Err(Error::Unsupported { .. })
+        ));
+    }
+
+    #[test]
+    fn classes_are_checked_with_an_enum_or_a_pattern() {
+        use serde_json::json;
+        let string = |pattern: &str| json!({"type": "string", "pattern": pattern});
+        let any_but = |pattern: &str| json!({"type": "string", "not": string(pattern), "maxLength": 1, "minLength": 1});
+        let byte_range =
+            |min: f64, max: f64| json!({"type": "integer", "maximum": max, "minimum": min});
+        for (regex, bytes, expected) in [
+            ("[a-c]", false, json!({"enum": ["a", "b", "c"]})),
+            ("[a-z]", false, string("^[a-z]$")),
+            (".", false, any_but("^[\\n]$")),
+            ("[^a]", false, any_but("^[a]$")),
+            ("(?-u:[a-c])", false, json!({"enum": ["a", "b", "c"]})),
+            ("(?-u:[\\x00-\\x7f])", false, string("^[\\u0000-\\u007f]$")),
+            (
+                "(?-u:[\\x80-\\x83])",
+                true,
+                json!({"enum": [128, 129, 130, 131]}),
+            ),
+            ("(?-u:[\\x80-\\xff])", true, byte_range(128.0, 255.0)),
+            ("(?s-u:.)", true, byte_range(0.0, 255.0)),
+        ] {
+            let options = Options {
+                bytes,
+                ..Options::default()
+            };
+            let schema = serde_json::to_value(compile(regex, &options).unwrap()).unwrap();
+            assert_eq!(
+                schema["definitions"]["S0"]["items"][0], expected,
+                "{}",
+                regex
+            );
+        }
+
+        // Outside byte mode, the characters consumed stand for themselves, which non-ASCII bytes
+        // can't.
+        let options = Options {
+            allow_invalid_utf8: true,
+            ..Options::default()
+        };
+        assert!(matches!(
+            compile("(?-u:[\\x80-\\xff])", &options),
+            Err(Error::Unsupported { .. })
+        ));
+    }
+
+    #[test]
+    fn dot_output_escapes_and_merges_labels() {
+        let nfa = generate_nfa("\"x|\\\\y|\nz| w|[a-c]|d", &Options::default()).unwrap();
+        let mut dot = vec![];
+        write_dot(&nfa, false, &mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        for label in [r#""\"""#, r#""\\""#, r#""\\n""#, r#""␣""#, r#""[a-d]""#] {
+            assert!(
+                dot.contains(&format!("[label={}]", label)),
+                "{} in {}",
+                label,
+                dot
+            );
+        }
+
+        let nfa = generate_nfa("ab|ac", &Options::default()).unwrap();
+        let mut dot = vec![];
+        write_dot_with_path(&nfa, "ad", false, &mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains(r#"label="\"ad\": rejected after 1 of 2 characters""#));
+        assert_eq!(dot.matches("penwidth=2").count(), 1);
+
+        // In byte mode, the path follows the UTF-8 encoding and bytes aren't shown as characters.
+        let options = Options {
+            bytes: true,
+            ..Options::default()
+        };
+        let nfa = generate_nfa("é|[\\x{80}-\\x{10FFFF}]x", &options).unwrap();
+        let mut dot = vec![];
+        write_dot_with_path(&nfa, "éy", true, &mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains(r#"label="\"éy\": rejected after 2 of 3 bytes""#));
+        assert_eq!(dot.matches("penwidth=2").count(), 2);
+        for label in [r#""\\xC3""#, r#""\\xA9""#] {
+            assert!(
+                dot.contains(&format!("label={}", label)),
+                "{} in {}",
+                label,
+                dot
+            );
+        }
+        assert!(!dot.contains('Ã'), "{}", dot);
+    }
+
+    #[test]
+    fn ecma_patterns_match_like_regex_crate() {
+        for regex in [
+            "a+b",
+            "(a|b)*c?",
+            "a{2,3}|[^a\\x{10000}-\\x{10FFFF}]",
+            "😀+|^a|b$",
+            r"\Aa*\z",
+            "(?i)A|é",
+            "[.*+?^${}()|\\[\\]\\\\/-]+",
+            "(ab)?|()",
+            "",
+            // Classes with astral characters.
+            ".",
+            "(?s).",
+            r"\d{2}-[^x]",
+            r"\D\S",
+            "😀|[😀-😂]",
+            "[^a😀]",
+        ] {
+            for match_mode in [MatchMode::Full, MatchMode::Search] {
+                let options = Options {
+                    match_mode,
+                    ..Options::default()
+                };
+                let pattern = match ecma_pattern(regex, &options).unwrap() {
+                    EcmaPattern::Supported(pattern) => pattern,
+                    EcmaPattern::Unsupported(constructs) => {
+                        panic!("{:?} is unsupported because of {:?}", regex, constructs)
+                    }
+                };
+                let schema = pattern_schema(&code_point_pattern(&pattern), Draft::Draft7);
+                let schema = serde_json::to_value(schema).unwrap();
+                let validator = jsonschema::JSONSchema::compile(&schema).unwrap();
+                let expected = regex_crate(regex, &options);
+                for input in inputs("aAbcé😀😁𝟙1x\n.*+?^${}()|[]\\/- ", 2) {
+                    assert_eq!(
+                        validator.is_valid(&input.clone().into()),
+                        expected.is_match(&input),
+                        "regex {:?}, pattern {:?}, input {:?}",
+                        regex,
+                        pattern,
+                        input,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ecma_patterns_report_unsupported_constructs() {
+        let unsupported = |regex| ecma_pattern(regex, &Options::default()).unwrap();
+        assert_eq!(
+            unsupported(r"(?m)^a$|\bb(?-u:\b)"),
+            EcmaPattern::Unsupported(vec![
+                "(?m:^)".to_string(),
+                "(?m:$)".to_string(),
+                r"\b".to_string(),
+                r"(?-u:\b)".to_string(),
+            ])
+        );
+        // Large classes with some astral characters are reported as written, once.
+        assert_eq!(
+            unsupported(r"\w|\pL|\w"),
+            EcmaPattern::Unsupported(vec![r"\w".to_string(), r"\pL".to_string()])
+        );
+    }
+
+    #[test]
+    fn astral_characters_are_kept_out_of_patterns_where_possible() {
+        fn patterns<'a>(value: &'a Value, result: &mut Vec<&'a str>) {
+            match value {
+                Value::Object(map) => {
+                    if let Some(Value::String(pattern)) = map.get("pattern") {
+                        result.push(pattern);
+                    }
+                    map.values().for_each(|v| patterns(v, result));
+                }
+                Value::Array(items) => items.iter().for_each(|v| patterns(v, result)),
+                _ => {}
+            }
+        }
+        // Large classes with some astral characters can only be written with ranges of them.
+        for (regex, portable) in [
+            (".", true),
+            ("[^a]", true),
+            (r"\d", true),
+            ("[a-z😀-😂]", true),
+            (r"\w", false),
+            (r"\pL", false),
+        ] {
+            let options = Options::default();
+            let schema = serde_json::to_value(compile(regex, &options).unwrap()).unwrap();
+            let mut found = vec![];
+            patterns(&schema, &mut found);
+            assert!(!found.is_empty(), "regex {:?}", regex);
+            for pattern in found {
+                assert!(!pattern.contains("\\u{"), "{:?}", pattern);
+                assert_eq!(
+                    pattern.chars().all(|c| c <= '\u{ffff}'),
+                    portable,
+                    "regex {:?}, pattern {:?}",
+                    regex,
+                    pattern
+                );
+            }
+            assert_schema_agrees_with_regex(regex, "a1\n😀😃𝟙\u{D7FF}\u{E000}", &options);
+        }
+        // Nor may ranges include surrogates, which are characters without the flag.
+        let schema = compile(r"[\x{D000}-\x{E100}]", &Options::default()).unwrap();
+        let schema = serde_json::to_string(&schema).unwrap();
+        assert!(
+            schema.contains(r"[\\ud000-\\ud7ff\\ue000-\\ue100]"),
+            "{}",
+            schema
+        );
+    }
+
+    #[test]
+    fn combined_schemas_validate_each_field_and_share_definitions() {
+        let fields = [("a b/c~", "a+"), ("bc", "(b|c)b"), ("again", "a+")];
+        let regexes: Vec<_> = fields
+            .iter()
+            .map(|(_, regex)| regex::Regex::new(&format!(r"\A(?:{})\z", regex)).unwrap())
+            .collect();
+        for (draft, jsonschema_draft) in [
+            (Draft::Draft7, jsonschema::Draft::Draft7),
+            (Draft::Draft201909, jsonschema::Draft::Draft201909),
+            (Draft::Draft202012, jsonschema::Draft::Draft202012),
+        ] {
+            for encoding in [Encoding::Cons, Encoding::Chunked, Encoding::Tree] {
+                let options = Options {
+                    draft,
+                    encoding,
+                    ..Options::default()
+                };
+                let schema =
+                    serde_json::to_value(compile_fields(fields, &options).unwrap()).unwrap();
+                let definitions = schema[if draft == Draft::Draft7 {
+                    "definitions"
+                } else {
+                    "$defs"
+                }]
+                .as_object()
+                .unwrap();
+                assert!(definitions.keys().any(|name| name.starts_with("a b/c~.")));
+                assert!(!definitions.keys().any(|name| name.starts_with("again.")));
+                let validator = jsonschema::JSONSchema::options()
+                    .with_draft(jsonschema_draft)
+                    .compile(&schema)
+                    .unwrap();
+                for x in inputs("abc", 3) {
+                    for y in inputs("abc", 3) {
+                        let values = [&x, &y, &x];
+                        let document: serde_json::Map<_, _> = fields
+                            .iter()
+                            .zip(values)
+                            .map(|((field, regex), input)| {
+                                (field.to_string(), encode(regex, input, &options).unwrap())
+                            })
+                            .collect();
+                        assert_eq!(
+                            validator.is_valid(&document.into()),
+                            regexes
+                                .iter()
+                                .zip(values)
+                                .all(|(r, input)| r.is_match(input)),
+                            "inputs {:?}, options {:?}",
+                            values,
+                            options
+                        );
+                    }
+                }
+            }
+        }
+        let error = compile_fields([("ok", "a"), ("bad", "(")], &Options::default()).unwrap_err();
+        assert_eq!(error.field, "bad");
+        let error =
+            compile_fields([("f", "a"), ("g", "b"), ("f", "bb")], &Options::default()).unwrap_err();
+        assert_eq!(error.field, "f");
+        assert!(matches!(error.error, Error::DuplicateField));
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate field")]
+    fn combining_fields_with_the_same_name_panics() {
+        let options = Options::default();
+        let schemas = [("f", "a"), ("f", "bb")]
+            .map(|(field, regex)| (field.to_string(), compile(regex, &options).unwrap()));
+        combine_schemas(schemas, options.draft);
+    }
+
+    #[test]
+    fn tree_encoding_nests_logarithmically() {
+        fn depth(value: &Value) -> usize {
+            match value {
+                Value::Array(items) => 1 + items.iter().map(depth).max().unwrap_or(0),
+                _ => 0,
+            }
+        }
+        let options = Options {
+            encoding: Encoding::Tree,
+            minimize: true,
+            ..Options::default()
+        };
+        let schema = serde_json::to_value(compile("(ab|c)*", &options).unwrap()).unwrap();
+        let validator = jsonschema::JSONSchema::compile(&schema).unwrap();
+        let input = "abc".repeat(10_000);
+        let encoded = encode("(ab|c)*", &input, &options).unwrap();
+        assert!(depth(&encoded) <= 17, "depth {}", depth(&encoded));
+        assert!(validator.is_valid(&encoded));
+        let encoded = encode("(ab|c)*", &format!("{}a", input), &options).unwrap();
+        assert!(!validator.is_valid(&encoded));
+    }
+
+    #[test]
+    fn literal_runs_are_consumed_in_one_step() {
+        let regex = r"https://api\.example\.com/(v1|v2)?";
+        let options = Options {
+            encoding: Encoding::Chunked,
+            ..Options::default()
+        };
+        let schema = serde_json::to_value(compile(regex, &options).unwrap()).unwrap();
+        assert!(
+            schema["definitions"].as_object().unwrap().len() <= 4,
+            "{}",
+            schema
+        );
+        let validator = jsonschema::JSONSchema::compile(&schema).unwrap();
+        let encoded = encode(regex, "https://api.example.com/v2", &options).unwrap();
+        assert_eq!(
+            encoded,
+            serde_json::json!(["https://api.example.com/", ["v2", []]])
+        );
+        assert!(validator.is_valid(&encoded));
+        for input in [
+            "https://api.example.com/",
+            "https://api.example.com/v3",
+            "http",
+        ] {
+            let encoded = encode(regex, input, &options).unwrap();
+            assert_eq!(
+                validator.is_valid(&encoded),
+                input == "https://api.example.com/"
+            );
+        }
+
+        // Where a literal can be reached by several paths, the run is split there.
+        let nfa = generate_nfa("(a|b)cd", &options).unwrap();
+        assert_eq!(
+            encode_chunked(&nfa, "acd"),
+            serde_json::json!(["a", ["cd", []]])
+        );
+        let nfa = generate_nfa(
+            "abc",
+            &Options {
+                bytes: true,
+                ..options
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            encode_chunked_bytes(&nfa, b"abc"),
+            serde_json::json!([[97, 98, 99], []])
+        );
+    }
+
+    #[test]
+    fn transitions_expose_consumed_ranges() {
+        let nfa = generate_nfa("[a-cx]|é", &Options::default()).unwrap();
+        let mut ranges: Vec<(char, char)> = nfa
+            .transitions(nfa.start())
+            .iter()
+            .flat_map(|t| t.class().unwrap().ranges().collect::<Vec<_>>())
+            .collect();
+        ranges.sort();
+        assert_eq!(ranges, [('a', 'c'), ('x', 'x'), ('é', 'é')]);
+        let class = nfa.transitions(nfa.start())[0].class().unwrap();
+        assert!(class.contains('b') || class.contains('é'));
+        assert!(!class.contains('d'));
+    }
+
+    #[test]
+    fn decoding_inverts_encoding() {
+        let options = Options {
+            encoding: Encoding::Tree,
+            ..Options::default()
+        };
+        let nfa = generate_nfa("[a-z]*", &options).unwrap();
+        for input in ["", "a", "ab", "abc", "not in the language", "héllo"] {
+            assert_eq!(
+                decode_input(&encode_input(input), Encoding::Cons).unwrap(),
+                input
+            );
+            assert_eq!(
+                decode_input(&encode_tree(&nfa, input), Encoding::Tree).unwrap(),
+                input
+            );
+            assert_eq!(
+                decode_input(&encode_chunked(&nfa, input), Encoding::Chunked).unwrap(),
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn decoding_reports_the_path_to_malformed_nodes() {
+        let error = |json: &str, encoding| {
+            let value = serde_json::from_str(json).unwrap();
+            decode_input(&value, encoding).unwrap_err().path
+        };
+        assert_eq!(error(r#""a""#, Encoding::Cons), "#");
+        assert_eq!(error(r#"["a", ["bc", []]]"#, Encoding::Cons), "#/1/0");
+        assert_eq!(error(r#"["a", ["b", [1]]]"#, Encoding::Cons), "#/1/1");
+        assert_eq!(error(r#"["a", []]"#, Encoding::Tree), "#/0");
+        assert_eq!(error(r#"[1, [0, "a", [2, "b"]]]"#, Encoding::Tree), "#/1/2");
+        assert_eq!(
+            error(r#"[1, [0, "a", [-2, "b", "c"]]]"#, Encoding::Tree),
+            "#/1/2/0"
+        );
+        assert_eq!(
+            error(r#"[1, [0, "a", [2, "b", 3]]]"#, Encoding::Tree),
+            "#/1/2/2"
+        );
+    }
+
+    #[test]
+    fn size_limits_produce_errors() {
+        let too_large = |regex: &str, options: Options| match compile(regex, &options) {
+            Err(Error::TooLarge { what, .. }) => what,
+            result => panic!("{:?} with {:?}: {:?}", regex, options, result),
+        };
+        let options = Options {
+            max_nfa_states: 10,
+            ..Options::default()
+        };
+        assert_eq!(too_large("abcdefghijklmnop", options), Limit::NfaStates);
+        assert!(compile(
+            "abcdefgh",
+            &Options {
+                max_nfa_states: 10,
+                ..Options::default()
+            }
+        )
+        .is_ok());
+        for determinize in [false, true] {
+            let options = Options {
+                max_dfa_states: 100,
+                determinize,
+                minimize: !determinize,
+                ..Options::default()
+            };
+            assert_eq!(too_large("(a|b)*a(a|b){8}", options), Limit::DfaStates);
+        }
+        let options = Options {
+            max_transitions: 20,
+            ..Options::default()
+        };
+        assert_eq!(too_large("(a|b|c|d){10}", options), Limit::Transitions);
+        let options = Options {
+            max_schema_bytes: 100,
+            ..Options::default()
+        };
+        assert_eq!(too_large("abc", options), Limit::SchemaBytes);
+        // The tree schema grows with the cube of the number of states, and must be rejected
+        // before it's built.
+        let options = Options {
+            encoding: Encoding::Tree,
+            ..Options::default()
+        };
+        assert_eq!(too_large("a{400}", options), Limit::SchemaBytes);
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_part_of_the_pattern() {
+        let span = |regex: &str| compile(regex, &Options::default()).unwrap_err().span();
+        assert_eq!(span("a(b"), Some(1..2));
+        assert_eq!(span("*a"), Some(0..0));
+        assert_eq!(span(r"\p{Foo}"), Some(0..7));
+        assert_eq!(span("a{2}(b{100}){1000}c"), Some(4..18));
+        assert_eq!(span("(a{100}){1000}b{2}"), Some(0..14));
+        let span = |regex: &str, options: Options| compile(regex, &options).unwrap_err().span();
+        let invalid_utf8 = || Options {
+            allow_invalid_utf8: true,
+            ..Options::default()
+        };
+        assert_eq!(span(r"abc(?-u:\xFF)d", invalid_utf8()), Some(8..12));
+        assert_eq!(span(r"a(?-u:[\x80-\xFF])", invalid_utf8()), Some(6..17));
+        let bytes = Options {
+            bytes: true,
+            ..Options::default()
+        };
+        assert_eq!(span(r"(?i)a|b\b", bytes), Some(7..9));
+    }
+
+    #[test]
+    fn definitions_are_named_in_breadth_first_order() {
+        let names = |regex: &str, options: &Options| -> Vec<String> {
+            let schema = serde_json::to_value(compile(regex, options).unwrap()).unwrap();
+            schema["definitions"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect()
+        };
+        let options = Options::default();
+        assert_eq!(
+            names("abcdefghijk", &options),
+            (0..12).map(|n| format!("S{}", n)).collect::<Vec<_>>()
+        );
+
+        // The order of alternatives doesn't change the numbering.
+        let schema = |regex| serde_json::to_value(compile(regex, &options).unwrap()).unwrap();
+        let (ab_cd, cd_ab) = (schema("(ab|cd)e"), schema("(cd|ab)e"));
+        for state in ["S1", "S2", "S3", "S4"] {
+            assert_eq!(ab_cd["definitions"][state], cd_ab["definitions"][state]);
+        }
+
+        // Named groups don't add states of their own unless asked for capture group definitions.
+        assert_eq!(names("(?P<a>x)|y", &options), ["S0", "S1"]);
+
+        let regex = r"(?P<year>\d{2})-(?P<month>[01]\d)|x";
+        for (determinize, minimize) in [(false, false), (true, false), (false, true)] {
+            let options = Options {
+                group_names: true,
+                determinize,
+                minimize,
+                ..Options::default()
+            };
+            assert_eq!(
+                names(regex, &options),
+                ["S0", "year_S1", "S2", "year_S3", "S4", "month_S5"]
+            );
+            assert_schema_agrees_with_regex(regex, "1-x", &options);
+        }
+    }
+
+    #[test]
+    fn capture_groups_get_definitions_and_annotations() {
+        let regex = "(?P<year>[0-9]{2})-(?P<md>(?P<month>[01][0-9])-x)?";
+        let expected = regex_crate(regex, &Options::default());
+        for (draft, encoding) in [
+            (Draft::Draft7, Encoding::Cons),
+            (Draft::Draft202012, Encoding::Chunked),
+            (Draft::Draft7, Encoding::Tree),
+        ] {
+            let options = Options {
+                draft,
+                encoding,
+                capture_groups: true,
+                ..Options::default()
+            };
+            let mut schema = serde_json::to_value(compile(regex, &options).unwrap()).unwrap();
+            let validator = jsonschema::JSONSchema::compile(&schema).unwrap();
+            for input in inputs("09-x", 6) {
+                let encoded = encode(regex, &input, &options).unwrap();
+                assert_eq!(validator.is_valid(&encoded), expected.is_match(&input));
+            }
+
+            // Each group's definition accepts what the group matches on its own.
+            let keyword = draft.definitions_keyword();
+            for (group, group_regex) in [("year", "[0-9]{2}"), ("md", "[01][0-9]-x")] {
+                let definition = format!("capture.{}", group);
+                assert_eq!(schema[keyword][&definition]["title"], group);
+                schema["$ref"] = format!("#/{}/{}", keyword, definition).into();
+                let validator = jsonschema::JSONSchema::compile(&schema).unwrap();
+                let expected = regex_crate(group_regex, &Options::default());
+                for input in inputs("19-x", 4) {
+                    let encoded = encode(group_regex, &input, &options).unwrap();
+                    assert_eq!(validator.is_valid(&encoded), expected.is_match(&input));
+                }
+            }
+        }
+
+        let options = Options {
+            capture_groups: true,
+            ..Options::default()
+        };
+        let schema = serde_json::to_value(compile(regex, &options).unwrap()).unwrap();
+        let mut annotations = vec![];
+        let mut values = vec![&schema];
+        while let Some(value) = values.pop() {
+            match value {
+                Value::Object(object) => {
+                    annotations.extend(object.get("x-capture").map(Value::to_string));
+                    values.extend(object.values());
+                }
+                Value::Array(items) => values.extend(items),
+                _ => {}
+            }
+        }
+        annotations.sort();
+        // Entering the month, and leaving it once, are annotated in the definition of `md`.
+        assert_eq!(
+            annotations,
+            [
+                r#"{"enter":["md","month"]}"#,
+                r#"{"enter":["month"]}"#,
+                r#"{"enter":["year"]}"#,
+                r#"{"leave":["md"]}"#,
+                r#"{"leave":["month"]}"#,
+                r#"{"leave":["month"]}"#,
+                r#"{"leave":["year"]}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn equivalent_regexes_minimize_to_the_same_schema() {
+        for regexes in [
+            &["(a|b)*", "[ab]*", "(a*b*)*", "(a|b|ab)*"][..],
+            &["a+", "aa*", "a*a", "(a+)+"],
+            &["(a|ab)(c|bc)", "abc|ac|abbc"],
+            &["a{2,3}", "aaa?", "aa|aaa"],
+            &["(a|b|ab)*c", "[ab]*c"],
+        ] {
+            let schemas: Vec<_> = regexes
+                .iter()
+                .map(|regex| {
+                    let options = Options {
+                        minimize: true,
+                        ..Options::default()
+                    };
+                    serde_json::to_value(compile(regex, &options).unwrap()).unwrap()
+                })
+                .collect();
+            for (regex, schema) in regexes.iter().zip(&schemas) {
+                assert_eq!(schema, &schemas[0], "{:?} vs {:?}", regex, regexes[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn captures_match_like_regex_crate() {
+        for regex in [
+            "(a|ab)(c|bc)",
+            "(?P<x>a*)(?P<y>a*)",
+            "(a*?)(a*)",
+            "(a{1,3})(a{1,3}?)",
+            "(?:(a)|b)+",
+            "(a*)*",
+            "(a?)+?",
+            "((a)|(b))*",
+            r"(\b)(a+)(\B)",
+            "(?m)(^)(b*)($)",
+            "()",
+        ] {
+            for match_mode in [MatchMode::Full, MatchMode::Search] {
+                let options = Options {
+                    match_mode,
+                    multi_line: true,
+                    ..Options::default()
+                };
+                let expected = regex_crate(regex, &options);
+                let matcher = CaptureMatcher::new(regex, &options).unwrap();
+                for input in inputs("ab\n", 4) {
+                    let spans = matcher
+                        .captures(&encode_input(&input))
+                        .unwrap()
+                        .map(|c| c.groups.into_iter().map(|g| g.span).collect::<Vec<_>>());
+                    let expected_spans = expected
+                        .captures(&input)
+                        .map(|c| c.iter().map(|m| m.map(|m| m.range())).collect());
+                    assert_eq!(
+                        spans, expected_spans,
+                        "regex {:?}, input {:?}, {:?}",
+                        regex, input, match_mode
+                    );
+                }
+            }
+        }
+
+        // Inputs are decoded with the encoding and in the mode of the schema.
+        let options = Options {
+            bytes: true,
+            encoding: Encoding::Tree,
+            ..Options::default()
+        };
+        let regex = "(?P<first>.)(?P<rest>.*)";
+        let matcher = CaptureMatcher::new(regex, &options).unwrap();
+        let found = matcher
+            .captures(&encode(regex, "éa", &options).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.name("first"), Some(0..2));
+        assert_eq!(found.name("rest"), Some(2..3));
+        assert_eq!(found.groups[1].name.as_deref(), Some("first"));
+    }
+}
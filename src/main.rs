@@ -1,12 +1,27 @@
 use clap::{Parser, Subcommand};
-use regex_syntax::hir::{Hir, HirKind, Literal, RepetitionKind};
-use schemars::schema::{
-    ArrayValidation, InstanceType, RootSchema, Schema, SchemaObject, SubschemaValidation,
+use regex_to_json_schema::{
+    code_point_pattern, combine_schemas, compile, decode_input, decode_input_bytes, ecma_pattern,
+    encode, encode_input, encode_input_bytes, generate_nfa, pattern_schema, write_dot,
+    write_dot_with_path, CaptureMatcher, Captures, Draft, EcmaPattern, Encoder, Encoding, Error,
+    MatchMode, Options,
 };
+use schemars::schema::RootSchema;
 use serde_json::Value;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
+#[clap(after_help = "EXIT STATUS:
+    0    success
+    2    invalid arguments
+    3    the regex is invalid
+    4    the regex uses an unsupported construct
+    5    the regex exceeds a size limit
+    6    the encoded input is malformed
+    7    the schema and the regex crate disagree on some input
+    8    the regex crate rejects the regex, which `check` needs")]
 struct Args {
     #[clap(subcommand)]
     command: Command,
@@ -15,270 +30,724 @@ struct Args {
 #[derive(Subcommand)]
 enum Command {
     /// Convert the regular expression to NFA, and output it in DOT format.
-    Nfa { regex: String },
+    Nfa {
+        /// The regex, or `-` to read it from stdin.
+        #[clap(required_unless_present = "file")]
+        regex: Option<String>,
+        /// Read the regex from a file instead.
+        #[clap(long, conflicts_with = "regex")]
+        file: Option<PathBuf>,
+        #[clap(flatten)]
+        options: NfaOptions,
+        /// Highlight the path this input takes through the automaton.
+        #[clap(long)]
+        highlight: Option<String>,
+        /// Render the graph with the Graphviz `dot` program instead of outputting DOT.
+        #[clap(long, arg_enum)]
+        render: Option<RenderFormat>,
+    },
 
     /// Convert the regular expression to JSON Schema.
     /// To use the resulting schema, convert input string using the `convert-input` command.
-    JsonSchema { regex: String },
+    JsonSchema {
+        /// The regex, or `-` to read it from stdin.
+        #[clap(required_unless_present = "file")]
+        regex: Option<String>,
+        /// Read the regex from a file instead.
+        #[clap(long, conflicts_with = "regex")]
+        file: Option<PathBuf>,
+        #[clap(flatten)]
+        options: NfaOptions,
+        /// Validate plain strings with an ECMA-262 `pattern` instead, if the regex can be
+        /// expressed as one. Otherwise, the constructs preventing it are reported, and the schema
+        /// for encoded input is generated as usual.
+        #[clap(long)]
+        pattern: bool,
+    },
 
     /// Encode input as a JSON document suitable for validating against a schema generated by the
     /// `json-schema` command.
-    EncodeInput { input: String },
+    EncodeInput {
+        /// The input, or `-` to read it from stdin.
+        #[clap(required_unless_present = "file")]
+        input: Option<String>,
+        /// Read the input from a file instead. With `--bytes`, files and stdin are read verbatim.
+        #[clap(long, conflicts_with = "input")]
+        file: Option<PathBuf>,
+        /// The regex the schema was generated from, with the same options, or `-` to read it from
+        /// stdin. Required by the `chunked` and `tree` encodings.
+        #[clap(
+            long,
+            required_if_eq_any(&[("encoding", "chunked"), ("encoding", "tree")])
+        )]
+        regex: Option<String>,
+        #[clap(flatten)]
+        options: NfaOptions,
+    },
+
+    /// Validate inputs against the schema generated for the regex, and check that the regex crate
+    /// agrees with the result.
+    Check {
+        /// The regex, or `-` to read it from stdin.
+        regex: String,
+        inputs: Vec<String>,
+        /// A file with further inputs, one per line, or `-` to read them from stdin.
+        #[clap(long)]
+        inputs_file: Option<PathBuf>,
+        #[clap(flatten)]
+        options: NfaOptions,
+        /// Check the schema generated by `json-schema --pattern`.
+        #[clap(long)]
+        pattern: bool,
+        /// Also print what each capture group matched, as an object keyed by the groups' names or
+        /// numbers, and check that the regex crate agrees with it.
+        #[clap(long)]
+        captures: bool,
+    },
+
+    /// Decode a JSON document produced by the `encode-input` command back into the input string.
+    DecodeInput {
+        /// The encoded input, or `-` to read it from stdin.
+        #[clap(required_unless_present = "file")]
+        encoded: Option<String>,
+        /// Read the encoded input from a file instead.
+        #[clap(long, conflicts_with = "encoded")]
+        file: Option<PathBuf>,
+        /// The encoding the document was produced with.
+        #[clap(long, arg_enum, default_value = "cons")]
+        encoding: Encoding,
+        /// Decode a document produced with `--bytes`, and write the bytes verbatim.
+        #[clap(long)]
+        bytes: bool,
+    },
+
+    /// Convert many regexes to JSON Schema at once. Errors are reported for each regex, and the
+    /// exit status is that of the first one.
+    Batch {
+        /// The file to read the regexes from; stdin if `-` or not given.
+        file: Option<PathBuf>,
+        /// `lines` has one regex per line, named by its line number. In `json-lines`, each line is
+        /// either a JSON string with the regex, or an object with `regex` and optionally `name`.
+        /// Blank lines are skipped in both.
+        #[clap(long, arg_enum, default_value = "lines")]
+        input_format: BatchInput,
+        /// `lines` writes one schema per line, or `null` for a regex that failed to convert.
+        /// `document` writes a single object with the schemas keyed by name. `combined` writes one
+        /// schema for objects with each regex's encoded input under its name, in which the
+        /// regexes share identical definitions.
+        #[clap(long, arg_enum, default_value = "lines")]
+        output_format: BatchOutput,
+        #[clap(flatten)]
+        options: NfaOptions,
+        /// Generate ECMA-262 `pattern` schemas where possible, as in `json-schema --pattern`.
+        #[clap(long)]
+        pattern: bool,
+    },
 }
 
-fn main() {
-    let args = Args::parse();
-    match args.command {
-        Command::Nfa { regex } => {
-            let (nfa, start, end) = generate_nfa(&regex);
-            let state_mapping = renumber_states(&nfa, start);
-            print_dot(&nfa, &state_mapping, start, end);
-        }
-        Command::JsonSchema { regex } => {
-            let (nfa, start, end) = generate_nfa(&regex);
-            let schema = to_json_schema(&nfa, start, end);
-            serde_json::to_writer_pretty(std::io::stdout().lock(), &schema).unwrap();
-        }
-        Command::EncodeInput { input } => {
-            let encoded = encode_input(&input);
-            serde_json::to_writer(std::io::stdout().lock(), &encoded).unwrap();
-            println!();
-        }
-    }
+#[derive(clap::ArgEnum, Clone, Copy, Debug)]
+enum RenderFormat {
+    Svg,
+    Png,
 }
 
-fn encode_input(input: &str) -> Value {
-    input.chars().rfold(Vec::<()>::new().into(), |next, c| {
-        vec![c.to_string().into(), next].into()
-    })
+#[derive(clap::ArgEnum, Clone, Copy, Debug)]
+enum BatchInput {
+    Lines,
+    JsonLines,
+}
+
+#[derive(clap::ArgEnum, Clone, Copy, Debug)]
+enum BatchOutput {
+    Lines,
+    Document,
+    Combined,
 }
 
-fn generate_nfa(regex: &str) -> (Nfa, State, State) {
-    let hir = regex_syntax::Parser::new().parse(regex).unwrap();
-    let mut nfa = Nfa::default();
-    let start = nfa.new_state();
-    let end = nfa.new_state();
-    regex_to_nfa(&mut nfa, &hir, start, end);
-    (nfa, start, end)
+#[derive(clap::Args, Debug)]
+struct NfaOptions {
+    /// Match letters case-insensitively, as with the `i` flag.
+    #[clap(short = 'i', long)]
+    case_insensitive: bool,
+
+    /// Make `^` and `$` match at the beginning and end of lines, as with the `m` flag.
+    #[clap(short = 'm', long)]
+    multi_line: bool,
+
+    /// Make `.` match `\n`, as with the `s` flag.
+    #[clap(short = 's', long)]
+    dot_matches_new_line: bool,
+
+    /// Interpret the regex as ASCII rather than Unicode, as with the `u` flag turned off.
+    #[clap(long)]
+    no_unicode: bool,
+
+    /// Accept regexes which can match invalid UTF-8, such as `(?-u:\xFF)`. Only their ASCII
+    /// parts can be converted.
+    #[clap(long)]
+    allow_invalid_utf8: bool,
+
+    /// Maximum nesting depth of the regex.
+    #[clap(long, default_value_t = 250)]
+    nest_limit: u32,
+
+    /// Match bytes rather than characters, encoding inputs as arrays of byte values. Implies
+    /// `--allow-invalid-utf8`. Unicode word boundaries (`\b` and `\B` without `(?-u)`) aren't
+    /// supported in this mode.
+    #[clap(long)]
+    bytes: bool,
+
+    /// Maximum number of NFA states a single counted repetition (`{n}`, `{n,}`, `{n,m}`) may
+    /// expand to.
+    #[clap(long, default_value_t = 10_000)]
+    max_repetition_states: usize,
+
+    /// Maximum number of NFA states.
+    #[clap(long, default_value_t = 100_000)]
+    max_nfa_states: usize,
+
+    /// Maximum number of DFA states, with `--determinize` or `--minimize`.
+    #[clap(long, default_value_t = 10_000)]
+    max_dfa_states: usize,
+
+    /// Maximum number of transitions of the automaton.
+    #[clap(long, default_value_t = 1_000_000)]
+    max_transitions: usize,
+
+    /// Maximum size of the schema in bytes, serialized without whitespace.
+    #[clap(long, default_value_t = 64 << 20)]
+    max_schema_bytes: usize,
+
+    /// Convert the NFA to a DFA before output.
+    #[clap(long)]
+    determinize: bool,
+
+    /// Convert the NFA to the minimal DFA before output. Implies `--determinize`.
+    #[clap(long)]
+    minimize: bool,
+
+    /// Whether the regex has to match the whole input, or just some part of it (like
+    /// `Regex::is_match`).
+    #[clap(long, arg_enum, default_value = "full")]
+    match_mode: MatchMode,
+
+    /// How inputs are encoded as JSON documents. `cons` nests as deeply as the input is long;
+    /// `chunked` is like `cons`, but consumes runs of literal characters in one step; `tree` nests
+    /// logarithmically deep. Both `chunked` and `tree` need the regex for encoding.
+    #[clap(long, arg_enum, default_value = "cons")]
+    encoding: Encoding,
+
+    /// The JSON Schema draft to generate schemas for.
+    #[clap(long, arg_enum, default_value = "07")]
+    draft: Draft,
+
+    /// Prefix the names of definitions with the name of the capture group their states are in,
+    /// as in `year_S3` for `(?P<year>\d{4})`.
+    #[clap(long)]
+    group_names: bool,
+
+    /// Add a definition `capture.{name}` for each named capture group, accepting the strings it
+    /// matches, and annotate the steps of the encoded input which leave or enter groups with an
+    /// `x-capture` keyword.
+    #[clap(long)]
+    capture_groups: bool,
 }
 
-fn print_dot(nfa: &Nfa, state_mapping: &[State], start: State, end: State) {
-    println!("digraph {{");
-    println!("rankdir=LR");
-    println!("\"\" [shape=none]");
-    for (state, number) in state_mapping.iter().enumerate() {
-        println!("{} [label=\"{}\"]", state, number);
+impl From<NfaOptions> for Options {
+    fn from(args: NfaOptions) -> Self {
+        let mut options = Self::default();
+        options.case_insensitive = args.case_insensitive;
+        options.multi_line = args.multi_line;
+        options.dot_matches_new_line = args.dot_matches_new_line;
+        options.unicode = !args.no_unicode;
+        options.allow_invalid_utf8 = args.allow_invalid_utf8;
+        options.nest_limit = args.nest_limit;
+        options.bytes = args.bytes;
+        options.max_repetition_states = args.max_repetition_states;
+        options.max_nfa_states = args.max_nfa_states;
+        options.max_dfa_states = args.max_dfa_states;
+        options.max_transitions = args.max_transitions;
+        options.max_schema_bytes = args.max_schema_bytes;
+        options.determinize = args.determinize;
+        options.minimize = args.minimize;
+        options.match_mode = args.match_mode;
+        options.encoding = args.encoding;
+        options.draft = args.draft;
+        options.group_names = args.group_names;
+        options.capture_groups = args.capture_groups;
+        options
     }
-    println!("\"\" -> {}", start);
-    for (from, transitions) in nfa.states.iter().enumerate() {
-        println!(
-            "{} [shape={}]",
-            from,
-            if from == end {
-                "doublecircle"
-            } else {
-                "circle"
+}
+
+fn main() {
+    let args = Args::parse();
+    match args.command {
+        Command::Nfa {
+            regex,
+            file,
+            options,
+            highlight,
+            render,
+        } => {
+            let regex = argument(regex, file);
+            let options: Options = options.into();
+            let nfa = generate_nfa(&regex, &options).unwrap_or_else(|e| exit(&regex, e));
+            let mut dot = vec![];
+            match highlight {
+                Some(input) => write_dot_with_path(&nfa, &input, options.bytes, &mut dot).unwrap(),
+                None => write_dot(&nfa, options.bytes, &mut dot).unwrap(),
             }
-        );
-        for t in transitions {
-            match t {
-                Transition::Goto(to) => println!("{} -> {} [label=\" \"]", from, to),
-                Transition::Consume(input, to) => {
-                    println!("{} -> {} [label=\"{}\"]", from, to, input)
+            match render {
+                Some(format) => render_dot(&dot, format),
+                None => std::io::stdout().lock().write_all(&dot).unwrap(),
+            }
+        }
+        Command::JsonSchema {
+            regex,
+            file,
+            options,
+            pattern,
+        } => {
+            let regex = argument(regex, file);
+            let (schema, _) = generate_schema(&regex, &options.into(), pattern)
+                .unwrap_or_else(|e| exit(&regex, e));
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &schema).unwrap();
+        }
+        Command::EncodeInput {
+            input,
+            file,
+            regex,
+            options,
+        } => {
+            let options: Options = options.into();
+            let regex = regex.map(|regex| argument(Some(regex), None));
+            let encoded = if options.bytes {
+                let input = match (input, file) {
+                    (Some(input), _) if input != "-" => input.into_bytes(),
+                    (_, file) => read_bytes(file.as_deref().unwrap_or_else(|| Path::new("-"))),
+                };
+                match regex {
+                    Some(regex) => Encoder::new(&regex, &options)
+                        .unwrap_or_else(|e| exit(&regex, e))
+                        .encode_bytes(&input),
+                    None => encode_input_bytes(&input),
+                }
+            } else {
+                let input = argument(input, file);
+                match regex {
+                    Some(regex) => {
+                        encode(&regex, &input, &options).unwrap_or_else(|e| exit(&regex, e))
+                    }
+                    None => encode_input(&input),
                 }
+            };
+            serde_json::to_writer(std::io::stdout().lock(), &encoded).unwrap();
+            println!();
+        }
+        Command::Check {
+            regex,
+            mut inputs,
+            inputs_file,
+            options,
+            pattern,
+            captures,
+        } => {
+            let regex = argument(Some(regex), None);
+            if let Some(file) = inputs_file {
+                inputs.extend(read_file(&file).lines().map(String::from));
             }
+            check(&regex, &inputs, &options.into(), pattern, captures);
+        }
+        Command::DecodeInput {
+            encoded,
+            file,
+            encoding,
+            bytes,
+        } => {
+            let encoded = argument(encoded, file);
+            let decoded = serde_json::from_str(&encoded)
+                .map_err(|e| e.to_string())
+                .and_then(|value| {
+                    if bytes {
+                        decode_input_bytes(&value, encoding)
+                    } else {
+                        decode_input(&value, encoding).map(|mut decoded| {
+                            decoded.push('\n');
+                            decoded.into_bytes()
+                        })
+                    }
+                    .map_err(|e| e.to_string())
+                })
+                .unwrap_or_else(|message| {
+                    eprintln!("error: {}", message);
+                    std::process::exit(6)
+                });
+            std::io::stdout().lock().write_all(&decoded).unwrap();
+        }
+        Command::Batch {
+            file,
+            input_format,
+            output_format,
+            options,
+            pattern,
+        } => {
+            let contents = read_file(file.as_deref().unwrap_or_else(|| Path::new("-")));
+            batch(
+                &contents,
+                input_format,
+                output_format,
+                &options.into(),
+                pattern,
+            );
         }
     }
-    println!("}}");
 }
 
-type State = usize;
-
-#[derive(Debug)]
-enum Transition {
-    Goto(State),
-    Consume(char, State),
-}
-
-#[derive(Debug, Default)]
-struct Nfa {
-    states: Vec<Vec<Transition>>,
+/// Render the DOT graph `dot` in `format` with the Graphviz `dot` program, writing the result to
+/// stdout.
+fn render_dot(dot: &[u8], format: RenderFormat) {
+    let format = match format {
+        RenderFormat::Svg => "-Tsvg",
+        RenderFormat::Png => "-Tpng",
+    };
+    let status = std::process::Command::new("dot")
+        .arg(format)
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            child.stdin.take().unwrap().write_all(dot)?;
+            child.wait()
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("error: can't run dot: {}", e);
+            std::process::exit(1)
+        });
+    if !status.success() {
+        eprintln!("error: dot failed with {}", status);
+        std::process::exit(1);
+    }
 }
 
-impl Nfa {
-    fn num_states(&self) -> usize {
-        self.states.len()
+/// The contents of the file at `path`, or of stdin if `path` is `-`.
+fn read_bytes(path: &Path) -> Vec<u8> {
+    let mut contents = vec![];
+    let result = if path == Path::new("-") {
+        std::io::stdin().read_to_end(&mut contents)
+    } else {
+        std::fs::File::open(path).and_then(|mut file| file.read_to_end(&mut contents))
+    };
+    if let Err(e) = result {
+        eprintln!("error: can't read {}: {}", path.display(), e);
+        std::process::exit(1);
     }
+    contents
+}
 
-    fn new_state(&mut self) -> State {
-        let s = self.states.len();
-        self.states.push(Default::default());
-        s
-    }
+/// Like `read_bytes`, for text files.
+fn read_file(path: &Path) -> String {
+    String::from_utf8(read_bytes(path)).unwrap_or_else(|_| {
+        eprintln!("error: {} is not valid UTF-8", path.display());
+        std::process::exit(1)
+    })
+}
 
-    fn add_transition(&mut self, from: State, t: Transition) {
-        self.states[from].push(t);
+/// A positional argument given on the command line, as `-` to read it from stdin, or through
+/// `--file`. The newline ending a file isn't part of the value.
+fn argument(value: Option<String>, file: Option<PathBuf>) -> String {
+    let path = match (value, file) {
+        (Some(value), _) if value != "-" => return value,
+        (_, Some(path)) => path,
+        _ => PathBuf::from("-"),
+    };
+    let mut contents = read_file(&path);
+    if contents.ends_with('\n') {
+        contents.pop();
+        if contents.ends_with('\r') {
+            contents.pop();
+        }
     }
+    contents
 }
 
-fn regex_to_nfa(nfa: &mut Nfa, r: &Hir, mut start: State, end: State) {
-    match r.kind() {
-        HirKind::Empty => nfa.add_transition(start, Transition::Goto(end)),
-        HirKind::Class(_) => unimplemented!("character classes not supported"),
-        HirKind::Group(g) => regex_to_nfa(nfa, &g.hir, start, end),
-        HirKind::Anchor(_) => unimplemented!("anchors not supported"),
-        HirKind::Concat(xs) => {
-            for (i, x) in xs.iter().enumerate() {
-                let next = if i == xs.len() - 1 {
-                    end
-                } else {
-                    nfa.new_state()
-                };
-                regex_to_nfa(nfa, x, start, next);
-                start = next;
-            }
+/// Convert each regex in `contents`, and write the schemas in `output_format`.
+fn batch(
+    contents: &str,
+    input_format: BatchInput,
+    output_format: BatchOutput,
+    options: &Options,
+    pattern: bool,
+) {
+    let mut status = 0;
+    let mut names = HashSet::new();
+    let mut schemas = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
         }
-        HirKind::Literal(lit) => {
-            let c = match lit {
-                Literal::Unicode(c) => *c,
-                Literal::Byte(b) => *b as char,
-            };
-            nfa.add_transition(start, Transition::Consume(c, end));
-        }
-        HirKind::Repetition(rep) => match rep.kind {
-            RepetitionKind::ZeroOrOne => {
-                regex_to_nfa(nfa, &rep.hir, start, end);
-                nfa.add_transition(start, Transition::Goto(end));
-            }
-            RepetitionKind::ZeroOrMore => {
-                regex_to_nfa(nfa, &rep.hir, start, start);
-                nfa.add_transition(start, Transition::Goto(end));
+        let entry = match input_format {
+            BatchInput::Lines => Ok(((i + 1).to_string(), line.to_string())),
+            BatchInput::JsonLines => batch_entry(line)
+                .map(|(name, regex)| (name.unwrap_or_else(|| (i + 1).to_string()), regex)),
+        };
+        let schema = match entry {
+            Ok((name, _)) if names.contains(&name) => {
+                eprintln!("error in {}: duplicate name", name);
+                status = status.max(1);
+                None
             }
-            RepetitionKind::OneOrMore => {
-                regex_to_nfa(nfa, &rep.hir, start, end);
-                nfa.add_transition(end, Transition::Goto(start));
-            }
-            RepetitionKind::Range(_) => unimplemented!(),
-        },
-        HirKind::Alternation(branches) => {
-            for branch in branches {
-                regex_to_nfa(nfa, branch, start, end);
+            Ok((name, regex)) => match generate_schema(&regex, options, pattern) {
+                Ok((schema, _)) => {
+                    names.insert(name.clone());
+                    Some((name, schema))
+                }
+                Err(e) => {
+                    eprintln!("error in {}: {}", name, e);
+                    show_span(&regex, &e);
+                    if status == 0 {
+                        status = exit_status(&e);
+                    }
+                    None
+                }
+            },
+            Err(message) => {
+                eprintln!("error on line {}: {}", i + 1, message);
+                status = status.max(1);
+                None
             }
+        };
+        match output_format {
+            BatchOutput::Lines => match schema {
+                Some((_, schema)) => println!("{}", serde_json::to_string(&schema).unwrap()),
+                None => println!("null"),
+            },
+            BatchOutput::Document | BatchOutput::Combined => schemas.extend(schema),
         }
-        HirKind::WordBoundary(_) => unimplemented!("word boundary not supported"),
     }
+    match output_format {
+        BatchOutput::Lines => {}
+        BatchOutput::Document => {
+            let document: BTreeMap<_, _> = schemas.into_iter().collect();
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &document).unwrap();
+            println!();
+        }
+        BatchOutput::Combined => {
+            let schema = combine_schemas(schemas, options.draft);
+            serde_json::to_writer_pretty(std::io::stdout().lock(), &schema).unwrap();
+            println!();
+        }
+    }
+    std::process::exit(status);
 }
 
-fn renumber_states(nfa: &Nfa, start: State) -> Vec<State> {
-    let mut state_mapping = vec![0; nfa.num_states()];
-    let mut next_id = 0;
-    let mut queued = vec![false; nfa.num_states()];
-    let mut queue = VecDeque::new();
-    queue.push_back(start);
-    queued[start] = true;
-    while let Some(state) = queue.pop_front() {
-        let id = next_id;
-        next_id += 1;
-        state_mapping[state] = id;
-        for t in &nfa.states[state] {
-            let target = *match t {
-                Transition::Consume(_, target) => target,
-                Transition::Goto(target) => target,
+/// The name, if any, and the regex of a `json-lines` batch entry.
+fn batch_entry(line: &str) -> Result<(Option<String>, String), String> {
+    match serde_json::from_str(line).map_err(|e| e.to_string())? {
+        Value::String(regex) => Ok((None, regex)),
+        Value::Object(mut object) => {
+            let name = match object.remove("name") {
+                None => None,
+                Some(Value::String(name)) => Some(name),
+                Some(_) => return Err("`name` is not a string".to_string()),
             };
-            if queued[target] {
-                continue;
+            match object.remove("regex") {
+                Some(Value::String(regex)) => Ok((name, regex)),
+                _ => Err("expected `regex` to be a string".to_string()),
             }
-            queue.push_back(target);
-            queued[target] = true;
         }
+        _ => Err("expected a string or an object".to_string()),
     }
-    state_mapping
 }
 
-fn to_json_schema(nfa: &Nfa, start: State, end: State) -> RootSchema {
-    RootSchema {
-        meta_schema: None,
-        schema: transition_to_schema(&Transition::Goto(start)),
-        definitions: nfa
-            .states
-            .iter()
-            .enumerate()
-            .map(|(state, transitions)| {
-                (
-                    to_state_name(state),
-                    one_of(
-                        transitions
-                            .iter()
-                            .map(|x| transition_to_schema(x).into())
-                            .chain(
-                                if state == end {
-                                    vec![end_schema()]
-                                } else {
-                                    vec![]
-                                }
-                                .into_iter(),
-                            )
-                            .collect(),
-                    ),
-                )
-            })
-            .collect(),
+/// Generate the schema for `regex`, or with `pattern`, an ECMA-262 pattern schema if possible.
+/// Returns the schema and the pattern, if it validates plain strings rather than encoded input.
+fn generate_schema(
+    regex: &str,
+    options: &Options,
+    pattern: bool,
+) -> Result<(RootSchema, Option<String>), Error> {
+    if pattern {
+        match ecma_pattern(regex, options)? {
+            EcmaPattern::Supported(pattern) => {
+                return Ok((pattern_schema(&pattern, options.draft), Some(pattern)))
+            }
+            EcmaPattern::Unsupported(constructs) => eprintln!(
+                "note: not an ECMA-262 pattern, because of {}; generating a schema for encoded \
+                 input",
+                constructs.join(", ")
+            ),
+        }
     }
+    Ok((compile(regex, options)?, None))
 }
 
-fn one_of(schemas: Vec<Schema>) -> Schema {
-    if schemas.len() == 1 {
-        schemas.into_iter().next().unwrap()
+/// The span each capture group matched, if it took part, by group number; `None` if there's no
+/// match.
+type MatchSpans = Option<Vec<Option<Range<usize>>>>;
+
+/// Validate each input against the schema for `regex`, print the results, and exit with an error
+/// if the regex crate disagrees with any of them.
+fn check(regex: &str, inputs: &[String], options: &Options, pattern: bool, with_captures: bool) {
+    let (mut schema, pattern) =
+        generate_schema(regex, options, pattern).unwrap_or_else(|e| exit(regex, e));
+    let plain = pattern.is_some();
+    if let Some(pattern) = pattern {
+        // The validator matches code points, and rejects the surrogates of the pattern.
+        schema = pattern_schema(&code_point_pattern(&pattern), options.draft);
+    }
+    let validator = jsonschema::JSONSchema::options()
+        .with_draft(match options.draft {
+            Draft::Draft7 => jsonschema::Draft::Draft7,
+            Draft::Draft201909 => jsonschema::Draft::Draft201909,
+            Draft::Draft202012 => jsonschema::Draft::Draft202012,
+        })
+        .compile(&serde_json::to_value(&schema).unwrap())
+        .expect("generated schema is invalid");
+    let expected = match options.match_mode {
+        // A comment at the end of the regex, as in `(?x) a # comment`, runs to the end of the line,
+        // so the closing parenthesis goes on a line of its own.
+        MatchMode::Full if ends_in_comment(regex, options) => format!("\\A(?:{}\n)\\z", regex),
+        MatchMode::Full => format!(r"\A(?:{})\z", regex),
+        MatchMode::Search => regex.to_string(),
+    };
+    let rejected = |e: regex::Error| -> ! {
+        eprintln!("error: the regex crate rejects the regex: {}", e);
+        std::process::exit(8)
+    };
+    // The spans of the capture groups, if the regex crate finds a match.
+    let regex_crate_captures: Box<dyn Fn(&str) -> MatchSpans> = if options.bytes {
+        let expected = regex::bytes::RegexBuilder::new(&expected)
+            .case_insensitive(options.case_insensitive)
+            .multi_line(options.multi_line)
+            .dot_matches_new_line(options.dot_matches_new_line)
+            .unicode(options.unicode)
+            .build()
+            .unwrap_or_else(|e| rejected(e));
+        Box::new(move |input| {
+            let found = expected.captures(input.as_bytes())?;
+            Some(found.iter().map(|m| Some(m?.range())).collect())
+        })
     } else {
-        SchemaObject {
-            subschemas: Some(Box::new(SubschemaValidation {
-                one_of: Some(schemas),
-                ..Default::default()
-            })),
-            ..Default::default()
+        let expected = regex::RegexBuilder::new(&expected)
+            .case_insensitive(options.case_insensitive)
+            .multi_line(options.multi_line)
+            .dot_matches_new_line(options.dot_matches_new_line)
+            .unicode(options.unicode)
+            .build()
+            .unwrap_or_else(|e| rejected(e));
+        Box::new(move |input| {
+            let found = expected.captures(input)?;
+            Some(found.iter().map(|m| Some(m?.range())).collect())
+        })
+    };
+    // Captures are found in encoded inputs, even when checking a pattern.
+    let encoder = (!plain || with_captures)
+        .then(|| Encoder::new(regex, options).unwrap_or_else(|e| exit(regex, e)));
+    let encode = |input: &str| encoder.as_ref().unwrap().encode(input);
+    let matcher = with_captures
+        .then(|| CaptureMatcher::new(regex, options).unwrap_or_else(|e| exit(regex, e)));
+
+    let mut disagreements = 0;
+    for input in inputs {
+        let instance = if plain {
+            Value::from(input.as_str())
+        } else {
+            encode(input)
+        };
+        let valid = validator.is_valid(&instance);
+        print!(
+            "{}\t{}",
+            if valid { "valid" } else { "invalid" },
+            Value::from(input.as_str())
+        );
+        let expected = regex_crate_captures(input);
+        let mut agrees = valid == expected.is_some();
+        if let Some(matcher) = &matcher {
+            let found = matcher
+                .captures(&encode(input))
+                .expect("encoded input doesn't decode");
+            print!(
+                "\t{}",
+                found
+                    .as_ref()
+                    .map_or(Value::Null, |c| captures_json(c, input))
+            );
+            let spans = found.map(|c| c.groups.into_iter().map(|g| g.span).collect());
+            agrees &= spans == expected;
+        }
+        if !agrees {
+            disagreements += 1;
+            print!("\tregex crate disagrees");
         }
-        .into()
+        println!();
+    }
+    if disagreements > 0 {
+        eprintln!(
+            "error: the regex crate disagrees with the schema on {} of {} inputs",
+            disagreements,
+            inputs.len()
+        );
+        std::process::exit(7);
     }
 }
 
-fn end_schema() -> Schema {
-    SchemaObject {
-        const_value: Some((&[] as &[()]).into()),
-        ..Default::default()
-    }
-    .into()
+/// Whether `regex`, which must be valid, ends in a comment of the `x` flag.
+fn ends_in_comment(regex: &str, options: &Options) -> bool {
+    regex_syntax::ast::parse::ParserBuilder::new()
+        .nest_limit(options.nest_limit)
+        .build()
+        .parse_with_comments(regex)
+        .is_ok_and(|parsed| {
+            parsed
+                .comments
+                .last()
+                .is_some_and(|comment| comment.span.end.offset == regex.len())
+        })
 }
 
-fn transition_to_schema(t: &Transition) -> SchemaObject {
-    match *t {
-        Transition::Goto(target) => SchemaObject {
-            reference: Some(format!("#/definitions/{}", to_state_name(target))),
-            ..Default::default()
-        },
-        Transition::Consume(c, target) => SchemaObject {
-            instance_type: Some(InstanceType::Array.into()),
-            array: Some(Box::new(ArrayValidation {
-                items: Some(
-                    vec![
-                        SchemaObject {
-                            const_value: Some(c.to_string().into()),
-                            ..Default::default()
-                        }
-                        .into(),
-                        transition_to_schema(&Transition::Goto(target)).into(),
-                    ]
-                    .into(),
-                ),
-                min_items: Some(2),
-                max_items: Some(2),
-                additional_items: Some(Box::new(false.into())),
-                ..Default::default()
-            })),
-            ..Default::default()
-        },
+/// The capture groups of a match as a JSON object, keyed by name or else by number, with the span
+/// and the text each group matched, or `null` if it didn't take part.
+fn captures_json(captures: &Captures, input: &str) -> Value {
+    let groups = captures.groups.iter().enumerate().map(|(i, group)| {
+        let key = group.name.clone().unwrap_or_else(|| i.to_string());
+        let value = group.span.as_ref().map_or(Value::Null, |span| {
+            serde_json::json!({
+                "start": span.start,
+                "end": span.end,
+                "text": String::from_utf8_lossy(&input.as_bytes()[span.clone()]),
+            })
+        });
+        (key, value)
+    });
+    Value::Object(groups.collect())
+}
+
+/// Report an error in `regex`, pointing at the offending part of it, and exit.
+fn exit(regex: &str, error: Error) -> ! {
+    eprintln!("error: {}", error);
+    show_span(regex, &error);
+    std::process::exit(exit_status(&error))
+}
+
+/// Print the part of `regex` an error is about, if known.
+fn show_span(regex: &str, error: &Error) {
+    if let Some(span) = error.span() {
+        // Show only the line containing the start of the span.
+        let line_start = regex[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = regex[span.start..]
+            .find('\n')
+            .map_or(regex.len(), |i| span.start + i);
+        let indent = regex[line_start..span.start].chars().count();
+        let width = regex[span.start..span.end.min(line_end)].chars().count();
+        eprintln!("    {}", &regex[line_start..line_end]);
+        eprintln!("    {}{}", " ".repeat(indent), "^".repeat(width.max(1)));
     }
 }
 
-fn to_state_name(s: State) -> String {
-    format!("S{}", s)
+fn exit_status(error: &Error) -> i32 {
+    match error {
+        Error::Parse { .. } => 3,
+        Error::Unsupported { .. } => 4,
+        Error::RepetitionTooLarge { .. } | Error::TooLarge { .. } => 5,
+        _ => 1,
+    }
 }
@@ -0,0 +1,240 @@
+//! Matching inputs against a regex, recording what its capture groups matched.
+
+use crate::epsilon::look_holds;
+use crate::nfa::{Boundary, Builder};
+use crate::{
+    decode_input, decode_input_bytes, parse, DecodeError, Encoding, Error, MatchMode, Nfa, Options,
+    State, Transition,
+};
+use regex_syntax::hir::{GroupKind, Hir, HirKind, Repetition, RepetitionKind, RepetitionRange};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// A match found by `CaptureMatcher::captures`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Captures {
+    /// The capture groups, numbered as in the `regex` crate: group 0 is the whole match, and the
+    /// others are numbered by their opening parentheses.
+    pub groups: Vec<Capture>,
+}
+
+/// What a capture group matched.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capture {
+    pub name: Option<String>,
+    /// The byte offsets of the part of the input the group matched, or `None` if the group didn't
+    /// take part in the match. A group which matched several times keeps its last match.
+    pub span: Option<Range<usize>>,
+}
+
+impl Captures {
+    /// The span matched by the group named `name`, if it took part in the match.
+    pub fn name(&self, name: &str) -> Option<Range<usize>> {
+        self.groups
+            .iter()
+            .find(|group| group.name.as_deref() == Some(name))
+            .and_then(|group| group.span.clone())
+    }
+}
+
+/// A regex prepared for finding what its capture groups match in encoded inputs, choosing between
+/// matches the way the `regex` crate does: the leftmost match, and among those the one preferred by
+/// the regex's alternations and repetitions.
+///
+/// Validation and extraction don't share an automaton. The one returned by `generate_nfa` has no
+/// `Goto` transitions, and removing them merges the states where groups start and end into their
+/// neighbours, and gathers transitions by state rather than in the order the regex prefers them;
+/// determinizing then sorts them by character. Keeping both through that would take tagged
+/// transitions, and there's no way to do so through determinizing. Capture groups are found with
+/// the automaton built before that instead, in which the transitions out of each state are still
+/// in order of priority, and the states where capture groups start and end are marked; the cost is
+/// building a second automaton for the regex, once.
+pub struct CaptureMatcher {
+    nfa: Nfa,
+    /// The names of the capture groups other than group 0, by group number minus one.
+    names: Vec<Option<String>>,
+    encoding: Encoding,
+    bytes: bool,
+    full: bool,
+}
+
+impl CaptureMatcher {
+    /// Prepare `regex` for matching inputs encoded for the schema `compile` generates for it with
+    /// `options`, under `options.match_mode` and the flags in `options`.
+    pub fn new(regex: &str, options: &Options) -> Result<Self, Error> {
+        let (ast, hir) = parse(regex, options)?;
+        let builder = Builder::new(options, &ast, &hir).marking_captures();
+        let mut nfa = Nfa::default();
+        let start = nfa.new_state();
+        let end = nfa.new_state();
+        builder.regex_to_nfa(&mut nfa, &hir, start, end)?;
+        nfa.add_transition(end, Transition::Accept);
+        let mut names = vec![];
+        capture_names(&hir, &mut names);
+        Ok(CaptureMatcher {
+            nfa,
+            names,
+            encoding: options.encoding,
+            bytes: options.bytes,
+            full: options.match_mode == MatchMode::Full,
+        })
+    }
+
+    /// Decode `encoded`, an input encoded as the schema expects (see `encode`), and find what each
+    /// capture group matched in it, if the regex matches it. Spans are byte offsets into the
+    /// decoded input, which with `Options::bytes` are the bytes matched.
+    pub fn captures(&self, encoded: &Value) -> Result<Option<Captures>, DecodeError> {
+        let (chars, offsets): (Vec<char>, Vec<usize>) = if self.bytes {
+            let input = decode_input_bytes(encoded, self.encoding)?;
+            let offsets = (0..=input.len()).collect();
+            (input.into_iter().map(char::from).collect(), offsets)
+        } else {
+            let input = decode_input(encoded, self.encoding)?;
+            let (chars, mut offsets): (Vec<_>, Vec<_>) =
+                input.char_indices().map(|(i, c)| (c, i)).unzip();
+            offsets.push(input.len());
+            (chars, offsets)
+        };
+        Ok(self.find(&chars, &offsets))
+    }
+
+    /// Find the captures in `chars`, whose byte offsets are `offsets`, followed by the length.
+    fn find(&self, chars: &[char], offsets: &[usize]) -> Option<Captures> {
+        let mut matcher = Matcher {
+            nfa: &self.nfa,
+            chars,
+            full: self.full,
+            visited: HashSet::new(),
+            slots: vec![None; 2 * (self.names.len() + 1)],
+        };
+        let starts = if self.full { 0..1 } else { 0..chars.len() + 1 };
+        for from in starts {
+            if let Some(to) = matcher.run(from) {
+                let slots = &matcher.slots;
+                let spans = [Some(from..to)]
+                    .into_iter()
+                    .chain(slots[2..].chunks(2).map(|slot| Some(slot[0]?..slot[1]?)));
+                let groups = [None]
+                    .into_iter()
+                    .chain(self.names.iter().cloned())
+                    .zip(spans)
+                    .map(|(name, span)| Capture {
+                        name,
+                        span: span.map(|span| offsets[span.start]..offsets[span.end]),
+                    })
+                    .collect();
+                return Some(Captures { groups });
+            }
+        }
+        None
+    }
+}
+
+/// Collect the names of the capture groups in `hir`, indexed by the group's number minus one.
+fn capture_names(hir: &Hir, names: &mut Vec<Option<String>>) {
+    match hir.kind() {
+        HirKind::Group(group) => {
+            let (index, name) = match &group.kind {
+                GroupKind::CaptureName { name, index } => (*index, Some(name.clone())),
+                GroupKind::CaptureIndex(index) => (*index, None),
+                GroupKind::NonCapturing => return capture_names(&group.hir, names),
+            };
+            let i = index as usize - 1;
+            if names.len() <= i {
+                names.resize(i + 1, None);
+            }
+            names[i] = name;
+            capture_names(&group.hir, names);
+        }
+        // Like the regex crate, leave out groups which can't match at all, unless later groups
+        // are numbered after them.
+        HirKind::Repetition(Repetition {
+            kind:
+                RepetitionKind::Range(RepetitionRange::Exactly(0) | RepetitionRange::Bounded(_, 0)),
+            ..
+        }) => {}
+        HirKind::Repetition(rep) => capture_names(&rep.hir, names),
+        HirKind::Alternation(xs) | HirKind::Concat(xs) => {
+            xs.iter().for_each(|x| capture_names(x, names))
+        }
+        HirKind::Empty
+        | HirKind::Literal(_)
+        | HirKind::Class(_)
+        | HirKind::Anchor(_)
+        | HirKind::WordBoundary(_) => {}
+    }
+}
+
+/// A backtracking search for the highest-priority accepting run, which explores each pair of a
+/// state and a position at most once: if a pair didn't lead to a match before, it won't now.
+struct Matcher<'a> {
+    nfa: &'a Nfa,
+    chars: &'a [char],
+    /// Whether the run has to consume all of the input.
+    full: bool,
+    visited: HashSet<(State, usize)>,
+    /// The positions where each capture group started and ended on the current run.
+    slots: Vec<Option<usize>>,
+}
+
+/// A step of the search.
+enum Frame {
+    /// Continue the run in the state at the position.
+    Explore(State, usize),
+    /// Accept at the position.
+    Accept(usize),
+    /// Backtrack past the start or end of a capture group, restoring its slot.
+    Restore(usize, Option<usize>),
+}
+
+impl Matcher<'_> {
+    /// Find the highest-priority accepting run starting at position `from`, and return the
+    /// position where it ends. The slots are left as they were on that run.
+    fn run(&mut self, from: usize) -> Option<usize> {
+        let mut stack = vec![Frame::Explore(self.nfa.start(), from)];
+        while let Some(frame) = stack.pop() {
+            let (state, pos) = match frame {
+                Frame::Explore(state, pos) => (state, pos),
+                Frame::Accept(pos) if !self.full || pos == self.chars.len() => return Some(pos),
+                Frame::Accept(_) => continue,
+                Frame::Restore(slot, value) => {
+                    self.slots[slot] = value;
+                    continue;
+                }
+            };
+            if !self.visited.insert((state, pos)) {
+                continue;
+            }
+            if let Some(boundary) = self.nfa.capture_boundary(state) {
+                let slot = match boundary {
+                    Boundary::Start(index) => 2 * index as usize,
+                    Boundary::End(index) => 2 * index as usize + 1,
+                };
+                stack.push(Frame::Restore(slot, self.slots[slot]));
+                self.slots[slot] = Some(pos);
+            }
+            let before = pos.checked_sub(1).map(|i| self.chars[i]);
+            let after = self.chars.get(pos).copied();
+            // The stack is last in, first out, so the transitions are pushed in reverse.
+            for t in self.nfa.transitions(state).iter().rev() {
+                stack.push(match t {
+                    Transition::Accept => Frame::Accept(pos),
+                    Transition::Goto(target) => Frame::Explore(*target, pos),
+                    Transition::Assert(look, target) if look_holds(*look, before, after) => {
+                        Frame::Explore(*target, pos)
+                    }
+                    Transition::Consume(_, target) | Transition::ConsumeClass(_, target)
+                        if after.is_some_and(|c| t.consumes(c)) =>
+                    {
+                        Frame::Explore(*target, pos + 1)
+                    }
+                    Transition::Assert(..)
+                    | Transition::Consume(..)
+                    | Transition::ConsumeClass(..) => continue,
+                });
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,807 @@
+//! The automaton representation and its construction from a parsed regex.
+
+use crate::error::{Limit, Span};
+use crate::{Error, Options};
+use regex_syntax::ast::Ast;
+use regex_syntax::hir::{
+    Anchor, Class, ClassBytes, ClassUnicode, ClassUnicodeRange, GroupKind, Hir, HirKind, Literal,
+    Repetition, RepetitionKind, RepetitionRange, WordBoundary,
+};
+use regex_syntax::utf8::Utf8Sequences;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+/// A state of an `Nfa`, as an index into its states.
+pub type State = usize;
+
+/// A transition out of an NFA state.
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Transition {
+    /// Go to the target state without consuming input.
+    Goto(State),
+    /// Consume the given character and go to the target state.
+    Consume(char, State),
+    /// Consume any character from the class and go to the target state.
+    ConsumeClass(CharClass, State),
+    /// Like `Goto`, but only if the assertion holds at the current position.
+    Assert(Look, State),
+    /// The input may end in this state.
+    Accept,
+}
+
+/// A zero-width assertion about the characters around the current position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Look {
+    StartText,
+    EndText,
+    StartLine,
+    EndLine,
+    WordBoundaryUnicode,
+    NotWordBoundaryUnicode,
+    WordBoundaryAscii,
+    NotWordBoundaryAscii,
+}
+
+/// A set of characters consumed by a transition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CharClass(pub(crate) ClassUnicode);
+
+impl CharClass {
+    /// The inclusive ranges of characters in the class, sorted and non-overlapping.
+    pub fn ranges(&self) -> impl Iterator<Item = (char, char)> + '_ {
+        self.0.iter().map(|r| (r.start(), r.end()))
+    }
+
+    /// Whether the class contains `c`.
+    pub fn contains(&self, c: char) -> bool {
+        let ranges = self.0.ranges();
+        let i = ranges.partition_point(|r| r.end() < c);
+        i < ranges.len() && ranges[i].start() <= c
+    }
+}
+
+impl Look {
+    /// The regex syntax for this assertion.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Look::StartText => "\\A",
+            Look::EndText => "\\z",
+            Look::StartLine => "(?m:^)",
+            Look::EndLine => "(?m:$)",
+            Look::WordBoundaryUnicode => "\\b",
+            Look::NotWordBoundaryUnicode => "\\B",
+            Look::WordBoundaryAscii => "(?-u:\\b)",
+            Look::NotWordBoundaryAscii => "(?-u:\\B)",
+        }
+    }
+}
+
+impl Transition {
+    /// The state this transition leads to, unless it's `Accept`.
+    pub fn target(&self) -> Option<State> {
+        match *self {
+            Transition::Goto(target) => Some(target),
+            Transition::Assert(_, target) => Some(target),
+            Transition::Consume(_, target) => Some(target),
+            Transition::ConsumeClass(_, target) => Some(target),
+            Transition::Accept => None,
+        }
+    }
+
+    /// The set of characters consumed by this transition, if it consumes any.
+    pub fn class(&self) -> Option<CharClass> {
+        self.unicode_class().map(CharClass)
+    }
+
+    /// Like `class`, as the parser's class type.
+    pub(crate) fn unicode_class(&self) -> Option<ClassUnicode> {
+        match self {
+            Transition::Consume(c, _) => Some(ClassUnicode::new([ClassUnicodeRange::new(*c, *c)])),
+            Transition::ConsumeClass(class, _) => Some(class.0.clone()),
+            Transition::Goto(_) | Transition::Assert(..) | Transition::Accept => None,
+        }
+    }
+
+    /// Whether this transition consumes `c`.
+    pub(crate) fn consumes(&self, c: char) -> bool {
+        match self {
+            Transition::Consume(x, _) => *x == c,
+            Transition::ConsumeClass(class, _) => class.contains(c),
+            Transition::Goto(_) | Transition::Assert(..) | Transition::Accept => false,
+        }
+    }
+
+    /// A transition consuming any character from `class`.
+    pub(crate) fn consume(class: ClassUnicode, target: State) -> Transition {
+        match class.ranges() {
+            [r] if r.start() == r.end() => Transition::Consume(r.start(), target),
+            _ => Transition::ConsumeClass(CharClass(class), target),
+        }
+    }
+}
+
+/// A nondeterministic finite automaton over characters. State 0 is the start state.
+///
+/// With `Options::bytes`, the automaton consumes bytes instead, each byte `b` standing for the
+/// character `b as char`, so that only characters up to U+00FF are consumed.
+///
+/// Automata returned by this crate have no `Goto` or `Assert` transitions left.
+#[derive(Debug, Default)]
+pub struct Nfa {
+    states: Vec<Vec<Transition>>,
+    /// The label of each transition, laid out like `states`.
+    transition_groups: Vec<Vec<GroupLabel>>,
+    /// The label of each state, combining the labels of the transitions into it.
+    groups: Vec<GroupLabel>,
+    /// The states where capture groups start and end, if the builder was asked to mark them.
+    capture_boundaries: HashMap<State, Boundary>,
+    num_transitions: usize,
+}
+
+/// A state entered exactly when a capture group starts or ends, with the group's index in the
+/// regex.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Boundary {
+    Start(u32),
+    End(u32),
+}
+
+/// Which named capture groups the characters consumed by a transition, or by all transitions into
+/// a state, belong to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) enum GroupLabel {
+    /// Nothing is known: the transition doesn't consume, or no transitions lead into the state.
+    #[default]
+    Unlabelled,
+    /// The characters are in these groups, from the outermost to the innermost.
+    Groups(Vec<String>),
+    /// The characters are in different groups.
+    Mixed,
+}
+
+impl GroupLabel {
+    /// Combine the labels of two sets of transitions.
+    pub(crate) fn merge(&mut self, other: &GroupLabel) {
+        match (&*self, other) {
+            (_, GroupLabel::Unlabelled) => {}
+            (GroupLabel::Unlabelled, _) => *self = other.clone(),
+            (GroupLabel::Groups(a), GroupLabel::Groups(b)) if a == b => {}
+            _ => *self = GroupLabel::Mixed,
+        }
+    }
+
+    /// The groups, unless they are mixed. Unlabelled counts as outside of all groups.
+    pub(crate) fn groups(&self) -> Option<&[String]> {
+        match self {
+            GroupLabel::Unlabelled => Some(&[]),
+            GroupLabel::Groups(groups) => Some(groups),
+            GroupLabel::Mixed => None,
+        }
+    }
+}
+
+impl Nfa {
+    pub fn start(&self) -> State {
+        0
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn num_transitions(&self) -> usize {
+        self.num_transitions
+    }
+
+    /// The transitions out of `state`. These are in the order the regex prefers them only until
+    /// `Goto` transitions are removed: that gathers them from the states reachable through `Goto`s
+    /// in order of state number, and determinizing sorts them by character, so the automata
+    /// returned by `generate_nfa` keep no order of priority.
+    pub fn transitions(&self, state: State) -> &[Transition] {
+        &self.states[state]
+    }
+
+    pub fn is_accepting(&self, state: State) -> bool {
+        self.states[state]
+            .iter()
+            .any(|t| matches!(t, Transition::Accept))
+    }
+
+    /// The innermost named capture group which all characters consumed on the way into `state`
+    /// belong to, if there is one.
+    pub fn group_name(&self, state: State) -> Option<&str> {
+        match &self.groups[state] {
+            GroupLabel::Groups(groups) => groups.last().map(String::as_str),
+            GroupLabel::Unlabelled | GroupLabel::Mixed => None,
+        }
+    }
+
+    /// The capture group starting or ending in `state`, if it marks one.
+    pub(crate) fn capture_boundary(&self, state: State) -> Option<Boundary> {
+        self.capture_boundaries.get(&state).copied()
+    }
+
+    /// The groups the characters consumed on the way into `state` belong to.
+    pub(crate) fn group_label(&self, state: State) -> &GroupLabel {
+        &self.groups[state]
+    }
+
+    /// The groups the characters consumed by the `i`th transition out of `state` belong to.
+    pub(crate) fn transition_group_label(&self, state: State, i: usize) -> &GroupLabel {
+        &self.transition_groups[state][i]
+    }
+
+    /// Record that the `i`th transition out of `state` consumes characters labelled `label`.
+    pub(crate) fn add_transition_group_label(
+        &mut self,
+        state: State,
+        i: usize,
+        label: &GroupLabel,
+    ) {
+        self.transition_groups[state][i].merge(label);
+        if let Some(target) = self.states[state][i].target() {
+            self.groups[target].merge(label);
+        }
+    }
+
+    /// A number for each state, counting in breadth-first order from the start state. The
+    /// transitions out of each state are followed in the order of the first character they
+    /// consume, so that the numbering doesn't depend on the order in which the automaton was
+    /// built. Unreachable states are numbered last.
+    pub(crate) fn breadth_first_numbering(&self) -> Vec<State> {
+        let mut numbering = vec![None; self.num_states()];
+        let mut next = 0;
+        let mut queue = VecDeque::from([self.start()]);
+        numbering[self.start()] = Some(next);
+        while let Some(state) = queue.pop_front() {
+            let mut transitions: Vec<&Transition> = self.transitions(state).iter().collect();
+            transitions.sort_by_key(|t| {
+                t.unicode_class()
+                    .and_then(|c| c.ranges().first().map(|r| r.start()))
+            });
+            for target in transitions.into_iter().filter_map(Transition::target) {
+                if numbering[target].is_none() {
+                    next += 1;
+                    numbering[target] = Some(next);
+                    queue.push_back(target);
+                }
+            }
+        }
+        numbering
+            .into_iter()
+            .map(|number| {
+                number.unwrap_or_else(|| {
+                    next += 1;
+                    next
+                })
+            })
+            .collect()
+    }
+
+    /// The same automaton, with the states renumbered by `breadth_first_numbering`.
+    pub(crate) fn renumbered(&self) -> Nfa {
+        let numbering = self.breadth_first_numbering();
+        let mut states: Vec<Vec<Transition>> = (0..self.num_states()).map(|_| vec![]).collect();
+        let mut transition_groups = vec![vec![]; self.num_states()];
+        let mut groups = vec![GroupLabel::Unlabelled; self.num_states()];
+        for (state, &number) in numbering.iter().enumerate() {
+            states[number] = self.states[state]
+                .iter()
+                .map(|t| match t {
+                    Transition::Goto(target) => Transition::Goto(numbering[*target]),
+                    Transition::Consume(c, target) => Transition::Consume(*c, numbering[*target]),
+                    Transition::ConsumeClass(class, target) => {
+                        Transition::ConsumeClass(class.clone(), numbering[*target])
+                    }
+                    Transition::Assert(look, target) => {
+                        Transition::Assert(*look, numbering[*target])
+                    }
+                    Transition::Accept => Transition::Accept,
+                })
+                .collect();
+            transition_groups[number] = self.transition_groups[state].clone();
+            groups[number] = self.groups[state].clone();
+        }
+        Nfa {
+            states,
+            transition_groups,
+            groups,
+            capture_boundaries: self
+                .capture_boundaries
+                .iter()
+                .map(|(&state, &boundary)| (numbering[state], boundary))
+                .collect(),
+            num_transitions: self.num_transitions,
+        }
+    }
+
+    /// For each state, whether it is inside a literal run: it is entered by a single transition
+    /// and left by a single transition, both of them `Consume` and in the same capture groups, so
+    /// every run through it consumes the characters on either side together. The start state never
+    /// is.
+    pub(crate) fn literal_run_states(&self) -> Vec<bool> {
+        let mut incoming = vec![0; self.num_states()];
+        let mut entered_by_literal = vec![true; self.num_states()];
+        for t in self.states.iter().flatten() {
+            if let Some(target) = t.target() {
+                incoming[target] += 1;
+                entered_by_literal[target] &= matches!(t, Transition::Consume(..));
+            }
+        }
+        (0..self.num_states())
+            .map(|s| {
+                s != self.start()
+                    && incoming[s] == 1
+                    && entered_by_literal[s]
+                    && matches!(self.states[s].as_slice(), [Transition::Consume(..)])
+                    && self.transition_groups[s][0] == self.groups[s]
+            })
+            .collect()
+    }
+
+    pub(crate) fn new_state(&mut self) -> State {
+        let s = self.states.len();
+        self.states.push(Default::default());
+        self.transition_groups.push(Default::default());
+        self.groups.push(Default::default());
+        s
+    }
+
+    pub(crate) fn add_transition(&mut self, from: State, t: Transition) {
+        self.add_labelled_transition(from, t, GroupLabel::Unlabelled);
+    }
+
+    /// Add a transition consuming characters labelled `label`.
+    pub(crate) fn add_labelled_transition(
+        &mut self,
+        from: State,
+        t: Transition,
+        label: GroupLabel,
+    ) {
+        self.states[from].push(t);
+        self.transition_groups[from].push(GroupLabel::Unlabelled);
+        self.add_transition_group_label(from, self.states[from].len() - 1, &label);
+        self.num_transitions += 1;
+    }
+
+    /// Check the size of the automaton against the limits in `options`, counting its states
+    /// against the limit `states`.
+    pub(crate) fn check_size(&self, states: Limit, options: &Options) -> Result<(), Error> {
+        let max_states = match states {
+            Limit::DfaStates => options.max_dfa_states,
+            _ => options.max_nfa_states,
+        };
+        if self.num_states() > max_states {
+            Err(Error::TooLarge {
+                what: states,
+                limit: max_states,
+            })
+        } else if self.num_transitions > options.max_transitions {
+            Err(Error::TooLarge {
+                what: Limit::Transitions,
+                limit: options.max_transitions,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Builds NFAs from a parsed regex.
+pub(crate) struct Builder<'a> {
+    options: &'a Options,
+    /// Spans of the repetitions in the pattern, keyed by the address of their `Hir` nodes.
+    repetition_spans: HashMap<*const Hir, Span>,
+    /// Spans of the literals, classes and assertions in the pattern, keyed likewise.
+    leaf_spans: HashMap<*const Hir, Span>,
+    /// The named groups around the sub-expression being built, from the outermost to the
+    /// innermost.
+    groups: RefCell<Vec<String>>,
+    /// Whether capture groups start and end in states of their own, marked as their boundaries.
+    mark_captures: bool,
+}
+
+impl<'a> Builder<'a> {
+    /// A builder for `hir`, which must have been translated from `ast`.
+    pub(crate) fn new(options: &'a Options, ast: &Ast, hir: &Hir) -> Self {
+        // Translation turns each repetition in the AST into exactly one in the HIR, keeping their
+        // order, so the spans can be matched up by position in a pre-order traversal. The same
+        // goes for literals, classes and assertions, though a literal may become a class.
+        let mut spans = vec![];
+        ast_repetition_spans(ast, &mut spans);
+        let mut hirs = vec![];
+        hir_repetitions(hir, &mut hirs);
+        assert_eq!(spans.len(), hirs.len());
+        let mut leaf_spans = vec![];
+        ast_leaf_spans(ast, &mut leaf_spans);
+        let mut leaves = vec![];
+        hir_leaves(hir, &mut leaves);
+        assert_eq!(leaf_spans.len(), leaves.len());
+        Builder {
+            options,
+            repetition_spans: hirs.into_iter().zip(spans).collect(),
+            leaf_spans: leaves.into_iter().zip(leaf_spans).collect(),
+            groups: RefCell::new(vec![]),
+            mark_captures: false,
+        }
+    }
+
+    /// Mark the states where capture groups start and end in the automata built (see
+    /// `Nfa::capture_boundary`). This adds a `Goto` transition on either side of each group.
+    pub(crate) fn marking_captures(mut self) -> Self {
+        self.mark_captures = true;
+        self
+    }
+
+    /// Add the consuming transition `t` out of `from`, labelled with the current groups.
+    pub(crate) fn add_consume(&self, nfa: &mut Nfa, from: State, t: Transition) {
+        let label = GroupLabel::Groups(self.groups.borrow().clone());
+        nfa.add_labelled_transition(from, t, label);
+    }
+
+    /// Add transitions from `start` to `end` matching `r`.
+    ///
+    /// Only transitions out of `start`, into `end` and between freshly allocated states are added,
+    /// so the same sub-expression can be built several times between different pairs of states.
+    pub(crate) fn regex_to_nfa(
+        &self,
+        nfa: &mut Nfa,
+        r: &Hir,
+        mut start: State,
+        end: State,
+    ) -> Result<(), Error> {
+        match r.kind() {
+            HirKind::Empty => nfa.add_transition(start, Transition::Goto(end)),
+            HirKind::Class(Class::Unicode(class)) if self.options.bytes => {
+                for range in class.iter() {
+                    // Each sequence is a chain of byte ranges, one for each byte of the encoding.
+                    for sequence in Utf8Sequences::new(range.start(), range.end()) {
+                        let sequence = sequence.as_slice();
+                        let mut from = start;
+                        for (i, bytes) in sequence.iter().enumerate() {
+                            let next = if i == sequence.len() - 1 {
+                                end
+                            } else {
+                                nfa.new_state()
+                            };
+                            let class = ClassUnicode::new([ClassUnicodeRange::new(
+                                bytes.start as char,
+                                bytes.end as char,
+                            )]);
+                            self.add_consume(nfa, from, Transition::consume(class, next));
+                            from = next;
+                        }
+                    }
+                }
+            }
+            HirKind::Class(class) => {
+                let class = match class {
+                    Class::Unicode(class) => class.clone(),
+                    Class::Bytes(class) if class.is_all_ascii() || self.options.bytes => {
+                        byte_class(class)
+                    }
+                    Class::Bytes(_) => return Err(self.unsupported(r)),
+                };
+                if !class.ranges().is_empty() {
+                    self.add_consume(nfa, start, Transition::consume(class, end));
+                }
+            }
+            HirKind::Group(g) => {
+                let (index, name) = match &g.kind {
+                    GroupKind::CaptureName { name, index } => (Some(*index), Some(name)),
+                    GroupKind::CaptureIndex(index) => (Some(*index), None),
+                    GroupKind::NonCapturing => (None, None),
+                };
+                let index = index.filter(|_| self.mark_captures);
+                let group_start = match index {
+                    Some(_) => {
+                        let group_start = nfa.new_state();
+                        nfa.add_transition(start, Transition::Goto(group_start));
+                        group_start
+                    }
+                    None => start,
+                };
+                // With `Options::capture_groups`, a named group ends in a state of its own, so that
+                // the characters leading into it are all in the group, even if others lead into
+                // `end`. So does a marked one, so that entering the state means leaving the group.
+                let labelled = name.is_some() && self.options.capture_groups;
+                let group_end = if index.is_some() || labelled {
+                    nfa.new_state()
+                } else {
+                    end
+                };
+                if let Some(name) = name {
+                    self.groups.borrow_mut().push(name.clone());
+                }
+                let result = self.regex_to_nfa(nfa, &g.hir, group_start, group_end);
+                if name.is_some() {
+                    self.groups.borrow_mut().pop();
+                }
+                result?;
+                if group_end != end {
+                    nfa.add_transition(group_end, Transition::Goto(end));
+                }
+                if let Some(index) = index {
+                    let boundaries = &mut nfa.capture_boundaries;
+                    boundaries.insert(group_start, Boundary::Start(index));
+                    boundaries.insert(group_end, Boundary::End(index));
+                }
+            }
+            HirKind::Anchor(anchor) => {
+                let look = match anchor {
+                    Anchor::StartText => Look::StartText,
+                    Anchor::EndText => Look::EndText,
+                    Anchor::StartLine => Look::StartLine,
+                    Anchor::EndLine => Look::EndLine,
+                };
+                nfa.add_transition(start, Transition::Assert(look, end));
+            }
+            HirKind::Concat(xs) => {
+                for (i, x) in xs.iter().enumerate() {
+                    let next = if i == xs.len() - 1 {
+                        end
+                    } else {
+                        nfa.new_state()
+                    };
+                    self.regex_to_nfa(nfa, x, start, next)?;
+                    start = next;
+                }
+            }
+            HirKind::Literal(Literal::Unicode(c)) if self.options.bytes => {
+                let mut buf = [0; 4];
+                let bytes = c.encode_utf8(&mut buf).as_bytes();
+                for (i, &b) in bytes.iter().enumerate() {
+                    let next = if i == bytes.len() - 1 {
+                        end
+                    } else {
+                        nfa.new_state()
+                    };
+                    self.add_consume(nfa, start, Transition::Consume(b as char, next));
+                    start = next;
+                }
+            }
+            HirKind::Literal(lit) => {
+                let c = match lit {
+                    Literal::Unicode(c) => *c,
+                    Literal::Byte(b) if b.is_ascii() || self.options.bytes => *b as char,
+                    Literal::Byte(_) => return Err(self.unsupported(r)),
+                };
+                self.add_consume(nfa, start, Transition::Consume(c, end));
+            }
+            HirKind::Repetition(rep) => {
+                let span = &self.repetition_spans[&(r as *const Hir)];
+                self.repetition_to_nfa(nfa, rep, span, start, end)?;
+            }
+            HirKind::Alternation(branches) => {
+                for branch in branches {
+                    self.regex_to_nfa(nfa, branch, start, end)?;
+                }
+            }
+            // Unicode word characters can't be told apart by looking at a single byte.
+            HirKind::WordBoundary(WordBoundary::Unicode | WordBoundary::UnicodeNegate)
+                if self.options.bytes =>
+            {
+                return Err(self.unsupported(r))
+            }
+            HirKind::WordBoundary(wb) => {
+                let look = match wb {
+                    WordBoundary::Unicode => Look::WordBoundaryUnicode,
+                    WordBoundary::UnicodeNegate => Look::NotWordBoundaryUnicode,
+                    WordBoundary::Ascii => Look::WordBoundaryAscii,
+                    WordBoundary::AsciiNegate => Look::NotWordBoundaryAscii,
+                };
+                nfa.add_transition(start, Transition::Assert(look, end));
+            }
+        }
+        nfa.check_size(Limit::NfaStates, self.options)
+    }
+
+    /// The error for `r`, a literal, class or assertion which can't be converted.
+    fn unsupported(&self, r: &Hir) -> Error {
+        Error::Unsupported {
+            construct: r.to_string(),
+            span: self.leaf_span(r),
+        }
+    }
+
+    /// The position in the pattern of `r`, a literal, class or assertion.
+    pub(crate) fn leaf_span(&self, r: &Hir) -> Option<Span> {
+        self.leaf_spans.get(&(r as *const Hir)).cloned()
+    }
+
+    /// Add transitions from `start` to `end` matching `rep`, which appears at `span` in the
+    /// pattern.
+    ///
+    /// The mandatory copies are chained one after another, and each optional copy can be skipped
+    /// by going straight to `end`. An unbounded tail loops through a fresh state, so that the loop
+    /// can't be entered from anywhere else. Skipping has priority over another copy only if the
+    /// repetition is lazy.
+    ///
+    /// When capture groups are marked, an unbounded repetition is built the way the regex crate
+    /// builds it, which changes what the groups match if `rep` can match the empty string: the
+    /// loop goes back through the last mandatory copy instead of a copy of its own, so a repeated
+    /// empty match ends it, and `x*` is built as `(?:x+)?`, so that `x` matches at least once
+    /// before it can be skipped.
+    fn repetition_to_nfa(
+        &self,
+        nfa: &mut Nfa,
+        rep: &Repetition,
+        span: &Span,
+        start: State,
+        end: State,
+    ) -> Result<(), Error> {
+        let (min, max) = match rep.kind {
+            RepetitionKind::ZeroOrOne => (0, Some(1)),
+            RepetitionKind::ZeroOrMore => (0, None),
+            RepetitionKind::OneOrMore => (1, None),
+            RepetitionKind::Range(RepetitionRange::Exactly(n)) => (n, Some(n)),
+            RepetitionKind::Range(RepetitionRange::AtLeast(n)) => (n, None),
+            RepetitionKind::Range(RepetitionRange::Bounded(n, m)) => (n, Some(m)),
+        };
+        let r = &rep.hir;
+        let optional_plus = self.mark_captures && min == 0 && max.is_none() && r.is_match_empty();
+        let min = if optional_plus { 1 } else { min };
+        let loop_through_last = self.mark_captures && min > 0 && max.is_none();
+        if optional_plus && !rep.greedy {
+            nfa.add_transition(start, Transition::Goto(end));
+        }
+        let limit = self.options.max_repetition_states;
+        let first_new_state = nfa.num_states();
+        let check_size = |nfa: &Nfa| {
+            if nfa.num_states() - first_new_state > limit {
+                Err(Error::RepetitionTooLarge {
+                    limit,
+                    span: span.clone(),
+                })
+            } else {
+                Ok(())
+            }
+        };
+
+        let mut current = start;
+        let mut loop_start = None;
+        for i in 0..min {
+            let next = if i == min - 1 && max == Some(min) {
+                end
+            } else {
+                nfa.new_state()
+            };
+            if loop_through_last && i == min - 1 {
+                let copy_start = nfa.new_state();
+                nfa.add_transition(current, Transition::Goto(copy_start));
+                current = copy_start;
+                loop_start = Some(copy_start);
+            }
+            self.regex_to_nfa(nfa, r, current, next)?;
+            current = next;
+            check_size(nfa)?;
+        }
+        match max {
+            Some(0) => nfa.add_transition(start, Transition::Goto(end)),
+            Some(max) => {
+                for i in min..max {
+                    if !rep.greedy {
+                        nfa.add_transition(current, Transition::Goto(end));
+                    }
+                    let next = if i == max - 1 { end } else { nfa.new_state() };
+                    self.regex_to_nfa(nfa, r, current, next)?;
+                    if rep.greedy {
+                        nfa.add_transition(current, Transition::Goto(end));
+                    }
+                    current = next;
+                    check_size(nfa)?;
+                }
+            }
+            None => match loop_start {
+                Some(loop_start) if rep.greedy => {
+                    nfa.add_transition(current, Transition::Goto(loop_start));
+                    nfa.add_transition(current, Transition::Goto(end));
+                }
+                Some(loop_start) => {
+                    nfa.add_transition(current, Transition::Goto(end));
+                    nfa.add_transition(current, Transition::Goto(loop_start));
+                }
+                None => {
+                    let loop_state = nfa.new_state();
+                    nfa.add_transition(current, Transition::Goto(loop_state));
+                    if !rep.greedy {
+                        nfa.add_transition(loop_state, Transition::Goto(end));
+                    }
+                    self.regex_to_nfa(nfa, r, loop_state, loop_state)?;
+                    if rep.greedy {
+                        nfa.add_transition(loop_state, Transition::Goto(end));
+                    }
+                    check_size(nfa)?;
+                }
+            },
+        }
+        if optional_plus && rep.greedy {
+            nfa.add_transition(start, Transition::Goto(end));
+        }
+        Ok(())
+    }
+}
+
+/// The characters standing for the bytes of a byte class; for ASCII bytes, these are the bytes'
+/// characters.
+pub(crate) fn byte_class(class: &ClassBytes) -> ClassUnicode {
+    ClassUnicode::new(
+        class
+            .iter()
+            .map(|r| ClassUnicodeRange::new(r.start() as char, r.end() as char)),
+    )
+}
+
+/// Collect the spans of repetitions in `ast`, in pre-order.
+fn ast_repetition_spans(ast: &Ast, spans: &mut Vec<Span>) {
+    match ast {
+        Ast::Repetition(rep) => {
+            spans.push(rep.span.start.offset..rep.span.end.offset);
+            ast_repetition_spans(&rep.ast, spans);
+        }
+        Ast::Group(group) => ast_repetition_spans(&group.ast, spans),
+        Ast::Alternation(alt) => alt.asts.iter().for_each(|x| ast_repetition_spans(x, spans)),
+        Ast::Concat(concat) => concat
+            .asts
+            .iter()
+            .for_each(|x| ast_repetition_spans(x, spans)),
+        Ast::Empty(_)
+        | Ast::Flags(_)
+        | Ast::Literal(_)
+        | Ast::Dot(_)
+        | Ast::Assertion(_)
+        | Ast::Class(_) => {}
+    }
+}
+
+/// Collect the spans of the literals, classes and assertions in `ast`, in pre-order.
+fn ast_leaf_spans(ast: &Ast, spans: &mut Vec<Span>) {
+    let span = match ast {
+        Ast::Literal(lit) => &lit.span,
+        Ast::Dot(span) => span,
+        Ast::Assertion(assertion) => &assertion.span,
+        Ast::Class(class) => class.span(),
+        Ast::Repetition(rep) => return ast_leaf_spans(&rep.ast, spans),
+        Ast::Group(group) => return ast_leaf_spans(&group.ast, spans),
+        Ast::Alternation(alt) => return alt.asts.iter().for_each(|x| ast_leaf_spans(x, spans)),
+        Ast::Concat(concat) => return concat.asts.iter().for_each(|x| ast_leaf_spans(x, spans)),
+        Ast::Empty(_) | Ast::Flags(_) => return,
+    };
+    spans.push(span.start.offset..span.end.offset);
+}
+
+/// Collect the literals, classes and assertions in `hir`, in pre-order.
+fn hir_leaves(hir: &Hir, result: &mut Vec<*const Hir>) {
+    match hir.kind() {
+        HirKind::Literal(_) | HirKind::Class(_) | HirKind::Anchor(_) | HirKind::WordBoundary(_) => {
+            result.push(hir)
+        }
+        HirKind::Repetition(rep) => hir_leaves(&rep.hir, result),
+        HirKind::Group(group) => hir_leaves(&group.hir, result),
+        HirKind::Alternation(xs) | HirKind::Concat(xs) => {
+            xs.iter().for_each(|x| hir_leaves(x, result))
+        }
+        HirKind::Empty => {}
+    }
+}
+
+/// Collect the repetitions in `hir`, in pre-order.
+fn hir_repetitions(hir: &Hir, result: &mut Vec<*const Hir>) {
+    match hir.kind() {
+        HirKind::Repetition(rep) => {
+            result.push(hir);
+            hir_repetitions(&rep.hir, result);
+        }
+        HirKind::Group(group) => hir_repetitions(&group.hir, result),
+        HirKind::Alternation(xs) | HirKind::Concat(xs) => {
+            xs.iter().for_each(|x| hir_repetitions(x, result))
+        }
+        HirKind::Empty
+        | HirKind::Literal(_)
+        | HirKind::Class(_)
+        | HirKind::Anchor(_)
+        | HirKind::WordBoundary(_) => {}
+    }
+}
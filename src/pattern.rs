@@ -0,0 +1,228 @@
+//! Translation of regexes to ECMA-262 patterns, for validating plain strings.
+
+use crate::nfa::{byte_class, Builder};
+use crate::schema::{
+    all_astral, class_pattern, class_ranges, class_size, escape_char, split_astral,
+    MAX_ASTRAL_ENUM_SIZE,
+};
+use crate::{Look, MatchMode};
+use regex_syntax::hir::{
+    Anchor, Class, ClassUnicode, Hir, HirKind, Literal, RepetitionKind, RepetitionRange,
+    WordBoundary,
+};
+use std::collections::HashSet;
+
+/// The result of translating a regex to an ECMA-262 pattern.
+#[derive(Debug, PartialEq)]
+pub enum EcmaPattern {
+    /// A pattern which matches the same strings as the regex, as a JSON Schema `pattern`, with or
+    /// without the `u` flag. Without it, strings are sequences of UTF-16 code units, so classes
+    /// which include all astral characters, like `.`, match their surrogate pairs with escapes such
+    /// as `\uD800`, which validators built on the `regex` crate reject (see
+    /// `code_point_pattern`).
+    Supported(String),
+    /// The regex uses constructs which have no ECMA-262 equivalent, or none which works with and
+    /// without the `u` flag, like large classes of some astral characters; these are listed as
+    /// they appear in the regex.
+    Unsupported(Vec<String>),
+}
+
+/// A class matching the surrogate pair of any astral character without the `u` flag, and nothing
+/// with it, followed by `|`.
+const SURROGATE_PAIR: &str = r"[\uD800-\uDBFF][\uDC00-\uDFFF]|";
+
+/// The range of surrogates, as written in a class.
+const SURROGATES: &str = r"\uD800-\uDFFF";
+
+/// Adapt `pattern`, a pattern from `ecma_pattern`, to validators which match code points and
+/// reject surrogates, like those built on the `regex` crate, by leaving out the parts which only
+/// deal with surrogate pairs. To those validators, the result means the same as `pattern` does
+/// with the `u` flag.
+pub fn code_point_pattern(pattern: &str) -> String {
+    // Surrogates can't be part of the regex, and escaped backslashes are written as `\\`, so
+    // the parts can't be confused with anything else. A class of nothing but surrogates would be
+    // left empty, which the `regex` crate doesn't accept.
+    pattern
+        .replace(SURROGATE_PAIR, "")
+        .replace(&format!("[^{}]", SURROGATES), r"[\s\S]")
+        .replace(SURROGATES, "")
+}
+
+/// Translate `hir`, parsed from `regex`, to an ECMA-262 pattern. In `MatchMode::Full` the pattern
+/// is anchored, since JSON Schema patterns search the string.
+pub(crate) fn to_ecma_pattern(
+    regex: &str,
+    hir: &Hir,
+    builder: &Builder,
+    match_mode: MatchMode,
+) -> EcmaPattern {
+    let mut translation = Translation {
+        regex,
+        builder,
+        unsupported: vec![],
+    };
+    let pattern = translation.ecma(hir);
+    let mut unsupported = translation.unsupported;
+    if !unsupported.is_empty() {
+        let mut seen = HashSet::new();
+        unsupported.retain(|construct| seen.insert(construct.clone()));
+        return EcmaPattern::Unsupported(unsupported);
+    }
+    EcmaPattern::Supported(match match_mode {
+        MatchMode::Full => format!("^(?:{})$", pattern),
+        MatchMode::Search => pattern,
+    })
+}
+
+struct Translation<'a> {
+    regex: &'a str,
+    /// Knows where the literals, classes and assertions of `regex` are.
+    builder: &'a Builder<'a>,
+    /// The constructs which couldn't be translated, so far.
+    unsupported: Vec<String>,
+}
+
+impl Translation<'_> {
+    fn ecma(&mut self, hir: &Hir) -> String {
+        match hir.kind() {
+            HirKind::Empty => String::new(),
+            HirKind::Literal(Literal::Unicode(c)) => escape(*c),
+            HirKind::Literal(Literal::Byte(b)) if b.is_ascii() => escape(*b as char),
+            HirKind::Class(Class::Unicode(class)) => self.class(hir, class),
+            HirKind::Class(Class::Bytes(class)) if class.is_all_ascii() => {
+                self.class(hir, &byte_class(class))
+            }
+            HirKind::Literal(Literal::Byte(_)) | HirKind::Class(Class::Bytes(_)) => {
+                self.unsupported(hir)
+            }
+            HirKind::Anchor(Anchor::StartText) => "^".to_string(),
+            HirKind::Anchor(Anchor::EndText) => "$".to_string(),
+            // Multi-line anchors would need the `m` flag, which a JSON Schema pattern can't set.
+            // ECMA-262 `\b` is the ASCII word boundary, but validators differ on that, and word
+            // boundaries aren't in the subset of ECMA-262 JSON Schema recommends anyway.
+            HirKind::Anchor(Anchor::StartLine) => self.unsupported_look(Look::StartLine),
+            HirKind::Anchor(Anchor::EndLine) => self.unsupported_look(Look::EndLine),
+            HirKind::WordBoundary(wb) => {
+                let look = match wb {
+                    WordBoundary::Unicode => Look::WordBoundaryUnicode,
+                    WordBoundary::UnicodeNegate => Look::NotWordBoundaryUnicode,
+                    WordBoundary::Ascii => Look::WordBoundaryAscii,
+                    WordBoundary::AsciiNegate => Look::NotWordBoundaryAscii,
+                };
+                self.unsupported_look(look)
+            }
+            HirKind::Group(g) => self.ecma(&g.hir),
+            HirKind::Concat(xs) => xs
+                .iter()
+                .map(|x| match x.kind() {
+                    HirKind::Alternation(_) => format!("(?:{})", self.ecma(x)),
+                    _ => self.ecma(x),
+                })
+                .collect(),
+            HirKind::Alternation(xs) => xs
+                .iter()
+                .map(|x| self.ecma(x))
+                .collect::<Vec<_>>()
+                .join("|"),
+            HirKind::Repetition(rep) => {
+                let operand = match rep.hir.kind() {
+                    // Without the `u` flag, an astral character is a pair of code units.
+                    HirKind::Literal(Literal::Unicode(c)) if *c > '\u{ffff}' => {
+                        format!("(?:{})", self.ecma(&rep.hir))
+                    }
+                    HirKind::Literal(_) | HirKind::Class(_) => self.ecma(&rep.hir),
+                    _ => format!("(?:{})", self.ecma(&rep.hir)),
+                };
+                let operator = match rep.kind {
+                    RepetitionKind::ZeroOrOne => "?".to_string(),
+                    RepetitionKind::ZeroOrMore => "*".to_string(),
+                    RepetitionKind::OneOrMore => "+".to_string(),
+                    RepetitionKind::Range(RepetitionRange::Exactly(n)) => format!("{{{}}}", n),
+                    RepetitionKind::Range(RepetitionRange::AtLeast(n)) => format!("{{{},}}", n),
+                    RepetitionKind::Range(RepetitionRange::Bounded(n, m)) => {
+                        format!("{{{},{}}}", n, m)
+                    }
+                };
+                operand + &operator
+            }
+        }
+    }
+
+    /// A single character or group matching `class`.
+    fn class(&mut self, hir: &Hir, class: &ClassUnicode) -> String {
+        // Without the `u` flag, astral characters are pairs of code units, which a class can't
+        // match, and with it, ranges of them can only be written with `\u{...}`, which is an error
+        // without it.
+        let (bmp, astral) = split_astral(class);
+        if class.ranges().is_empty() {
+            // `[]` isn't accepted by all validators.
+            "(?!)".to_string()
+        } else if astral.ranges().is_empty() {
+            class_pattern(class)
+        } else if class_size(&all_astral()) - class_size(&astral) <= MAX_ASTRAL_ENUM_SIZE {
+            // Leaving out surrogates, the negated class matches the BMP characters of `class`
+            // without the flag, and all astral characters too with it. Without the flag, these
+            // are matched as surrogate pairs instead. Outside of a class, an astral character
+            // matches itself either way, so the few missing from `class` are ruled out first.
+            let mut negated = bmp;
+            negated.negate();
+            negated.difference(&all_astral());
+            let mut excluded = all_astral();
+            excluded.difference(&astral);
+            let lookahead = if excluded.ranges().is_empty() {
+                String::new()
+            } else {
+                format!("(?!{})", astral_alternatives(&excluded).join("|"))
+            };
+            format!(
+                "(?:{}(?:{}[^{}{}]))",
+                lookahead,
+                SURROGATE_PAIR,
+                class_ranges(&negated),
+                SURROGATES
+            )
+        } else if class_size(&astral) <= MAX_ASTRAL_ENUM_SIZE {
+            // Outside of a class, an astral character matches itself either way.
+            let bmp = Some(bmp)
+                .filter(|bmp| !bmp.ranges().is_empty())
+                .map(|bmp| class_pattern(&bmp));
+            let alternatives: Vec<_> = bmp
+                .into_iter()
+                .chain(astral_alternatives(&astral))
+                .collect();
+            format!("(?:{})", alternatives.join("|"))
+        } else {
+            self.unsupported(hir)
+        }
+    }
+
+    /// Record `hir`, a literal or class, as unsupported, as it appears in the regex.
+    fn unsupported(&mut self, hir: &Hir) -> String {
+        let construct = match self.builder.leaf_span(hir) {
+            Some(span) => self.regex[span].to_string(),
+            None => hir.to_string(),
+        };
+        self.unsupported.push(construct);
+        String::new()
+    }
+
+    fn unsupported_look(&mut self, look: Look) -> String {
+        self.unsupported.push(look.as_str().to_string());
+        String::new()
+    }
+}
+
+/// The characters of `class`, which must be astral, each as a pattern matching it.
+fn astral_alternatives(class: &ClassUnicode) -> Vec<String> {
+    class
+        .iter()
+        .flat_map(|r| r.start()..=r.end())
+        .map(escape)
+        .collect()
+}
+
+fn escape(c: char) -> String {
+    let mut out = String::new();
+    escape_char(&mut out, c, "\\^$.|?*+()[]{}/");
+    out
+}
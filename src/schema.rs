@@ -0,0 +1,803 @@
+//! Generation of JSON Schemas from automata.
+
+use crate::error::Limit;
+use crate::{Draft, Encoding, Error, Nfa, Options, State, Transition};
+use regex_syntax::hir::{ClassUnicode, ClassUnicodeRange};
+use schemars::schema::{
+    ArrayValidation, InstanceType, NumberValidation, RootSchema, Schema, SchemaObject,
+    StringValidation, SubschemaValidation,
+};
+use schemars::Map;
+use std::collections::{HashSet, VecDeque};
+use std::io;
+
+/// Build a schema accepting the encodings (see `Encoding`) of exactly the strings accepted by
+/// `nfa`, which must not contain `Goto` or `Assert` transitions.
+pub fn to_json_schema(nfa: &Nfa, options: &Options) -> RootSchema {
+    match options.encoding {
+        Encoding::Cons | Encoding::Chunked => cons_schema(nfa, options),
+        Encoding::Tree => tree_schema(nfa, options),
+    }
+}
+
+/// Check that `schema` serializes to at most `options.max_schema_bytes` bytes, without keeping
+/// the serialization.
+pub(crate) fn check_size(schema: &RootSchema, options: &Options) -> Result<(), Error> {
+    struct Counter {
+        bytes: usize,
+        limit: usize,
+    }
+
+    impl io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.bytes += buf.len();
+            if self.bytes > self.limit {
+                return Err(io::ErrorKind::Other.into());
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let limit = options.max_schema_bytes;
+    serde_json::to_writer(&mut Counter { bytes: 0, limit }, schema).map_err(|_| Error::TooLarge {
+        what: Limit::SchemaBytes,
+        limit,
+    })
+}
+
+impl Draft {
+    /// The `$schema` URI of the draft's meta-schema.
+    pub fn meta_schema(self) -> &'static str {
+        match self {
+            Draft::Draft7 => "http://json-schema.org/draft-07/schema#",
+            Draft::Draft201909 => "https://json-schema.org/draft/2019-09/schema",
+            Draft::Draft202012 => "https://json-schema.org/draft/2020-12/schema",
+        }
+    }
+
+    /// The keyword holding the definitions referenced by the schema.
+    pub(crate) fn definitions_keyword(self) -> &'static str {
+        match self {
+            Draft::Draft7 => "definitions",
+            Draft::Draft201909 | Draft::Draft202012 => "$defs",
+        }
+    }
+}
+
+/// Assemble a schema for `draft` from the root schema and its definitions.
+pub(crate) fn root_schema(
+    schema: Schema,
+    definitions: Map<String, Schema>,
+    draft: Draft,
+) -> RootSchema {
+    let mut schema = schema.into_object();
+    let definitions = match draft {
+        Draft::Draft7 => definitions,
+        // schemars only knows `definitions`, so `$defs` has to go in as an extension.
+        Draft::Draft201909 | Draft::Draft202012 => {
+            schema.extensions.insert(
+                draft.definitions_keyword().to_string(),
+                serde_json::to_value(definitions).unwrap(),
+            );
+            Map::new()
+        }
+    };
+    RootSchema {
+        meta_schema: Some(draft.meta_schema().to_string()),
+        schema,
+        definitions,
+    }
+}
+
+/// Remove the definitions from `schema`, wherever `draft` keeps them.
+pub(crate) fn take_definitions(schema: &mut RootSchema, draft: Draft) -> Map<String, Schema> {
+    match draft {
+        Draft::Draft7 => std::mem::take(&mut schema.definitions),
+        Draft::Draft201909 | Draft::Draft202012 => schema
+            .schema
+            .extensions
+            .remove(draft.definitions_keyword())
+            .map(|definitions| serde_json::from_value(definitions).unwrap())
+            .unwrap_or_default(),
+    }
+}
+
+/// Schema referring to the definition `name`.
+pub(crate) fn reference(name: &str, draft: Draft) -> SchemaObject {
+    SchemaObject {
+        reference: Some(format!(
+            "#/{}/{}",
+            draft.definitions_keyword(),
+            escape_pointer(name)
+        )),
+        ..Default::default()
+    }
+}
+
+/// Escape `name` for use as a JSON Pointer token in a URI fragment.
+fn escape_pointer(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        match c {
+            '~' => out.push_str("~0"),
+            '/' => out.push_str("~1"),
+            'a'..='z'
+            | 'A'..='Z'
+            | '0'..='9'
+            | '-'
+            | '.'
+            | '_'
+            | '!'
+            | '$'
+            | '&'
+            | '\''
+            | '('
+            | ')'
+            | '*'
+            | '+'
+            | ','
+            | ';'
+            | '='
+            | ':'
+            | '@'
+            | '?' => out.push(c),
+            _ => {
+                for b in c.to_string().bytes() {
+                    out.push_str(&format!("%{:02X}", b));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Inverse of `escape_pointer`.
+pub(crate) fn unescape_pointer(token: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = token.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        match (b, tail) {
+            (b'%', [hi, lo, tail @ ..]) => {
+                let hex = std::str::from_utf8(&[*hi, *lo]).unwrap().to_string();
+                bytes.push(u8::from_str_radix(&hex, 16).unwrap());
+                rest = tail;
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes)
+        .unwrap()
+        .replace("~1", "/")
+        .replace("~0", "~")
+}
+
+/// Each state `n` gets a definition `S{n}`, accepting the encodings of inputs it accepts, named as
+/// described in `state_names`.
+///
+/// For `Encoding::Chunked`, a `Consume` transition into a literal run (see
+/// `Nfa::literal_run_states`) consumes the whole run in one step, so the states inside the run get
+/// no definitions.
+fn cons_schema(nfa: &Nfa, options: &Options) -> RootSchema {
+    let (draft, bytes) = (options.draft, options.bytes);
+    let chunked = options.encoding == Encoding::Chunked;
+    let names = state_names(nfa, options.group_names);
+    let inside = if chunked {
+        nfa.literal_run_states()
+    } else {
+        vec![false; nfa.num_states()]
+    };
+    root_schema(
+        transition_to_schema(&Transition::Goto(nfa.start()), &names, draft, bytes).into(),
+        (0..nfa.num_states())
+            .filter(|&state| !inside[state])
+            .map(|state| {
+                let transitions = nfa.transitions(state);
+                (
+                    names[state].clone(),
+                    alternatives(
+                        transitions
+                            .iter()
+                            .enumerate()
+                            .map(|(i, x)| {
+                                let mut schema = if chunked {
+                                    chunk_schema(nfa, x, &inside, &names, draft, bytes)
+                                } else {
+                                    transition_to_schema(x, &names, draft, bytes)
+                                };
+                                if options.capture_groups {
+                                    if let Some(annotation) = capture_annotation(nfa, state, i) {
+                                        schema
+                                            .extensions
+                                            .insert("x-capture".to_string(), annotation);
+                                    }
+                                }
+                                schema.into()
+                            })
+                            .collect(),
+                        is_exclusive(transitions),
+                    ),
+                )
+            })
+            .collect(),
+        draft,
+    )
+}
+
+/// The `x-capture` annotation (see `Options::capture_groups`) of a step out of `state` starting
+/// with its `i`th transition, if the step leaves or enters any named capture groups, and the
+/// automaton tells which.
+fn capture_annotation(nfa: &Nfa, state: State, i: usize) -> Option<serde_json::Value> {
+    let before = nfa.group_label(state).groups()?;
+    let after = match nfa.transitions(state)[i] {
+        Transition::Accept => &[],
+        _ => nfa.transition_group_label(state, i).groups()?,
+    };
+    let common = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let mut annotation = serde_json::Map::new();
+    if common < before.len() {
+        let left: Vec<&str> = before[common..].iter().rev().map(String::as_str).collect();
+        annotation.insert("leave".to_string(), left.into());
+    }
+    if common < after.len() {
+        annotation.insert("enter".to_string(), after[common..].into());
+    }
+    if annotation.is_empty() {
+        None
+    } else {
+        Some(annotation.into())
+    }
+}
+
+/// Schema for a step of `Encoding::Chunked` starting with the transition `t`, which continues
+/// through the states marked in `inside`. A chunk is a string, or with `bytes`, an array of byte
+/// values.
+fn chunk_schema(
+    nfa: &Nfa,
+    t: &Transition,
+    inside: &[bool],
+    names: &[String],
+    draft: Draft,
+    bytes: bool,
+) -> SchemaObject {
+    match *t {
+        Transition::Consume(c, mut target) => {
+            let mut chunk = vec![c];
+            while inside[target] {
+                match nfa.transitions(target) {
+                    [Transition::Consume(c, next)] => {
+                        chunk.push(*c);
+                        target = *next;
+                    }
+                    _ => unreachable!("states inside literal runs have a single `Consume`"),
+                }
+            }
+            let chunk = if bytes {
+                chunk.iter().map(|&c| c as u32).collect::<Vec<_>>().into()
+            } else {
+                chunk.iter().collect::<String>().into()
+            };
+            consume_schema(
+                SchemaObject {
+                    const_value: Some(chunk),
+                    ..Default::default()
+                },
+                &names[target],
+                draft,
+            )
+        }
+        Transition::ConsumeClass(ref class, target) if bytes => consume_schema(
+            tuple_schema(vec![class_schema(&class.0, true).into()], draft),
+            &names[target],
+            draft,
+        ),
+        _ => transition_to_schema(t, names, draft, bytes),
+    }
+}
+
+/// Whether at most one of the transitions can match any given input, which is the case if none
+/// of them is a `Goto` and they consume disjoint sets of characters.
+fn is_exclusive(transitions: &[Transition]) -> bool {
+    let mut seen = ClassUnicode::empty();
+    let mut accepts = false;
+    for t in transitions {
+        match t {
+            Transition::Goto(_) | Transition::Assert(..) => return false,
+            Transition::Accept if accepts => return false,
+            Transition::Accept => accepts = true,
+            _ => {
+                let class = t.unicode_class().unwrap();
+                let mut overlap = seen.clone();
+                overlap.intersect(&class);
+                if !overlap.ranges().is_empty() {
+                    return false;
+                }
+                seen.union(&class);
+            }
+        }
+    }
+    true
+}
+
+/// Combine schemas of a state's transitions. `oneOf` is used only if the transitions are known to
+/// be exclusive, since it rejects inputs matched by more than one of them.
+fn alternatives(schemas: Vec<Schema>, exclusive: bool) -> Schema {
+    match schemas.len() {
+        0 => false.into(),
+        1 => schemas.into_iter().next().unwrap(),
+        _ => SchemaObject {
+            subschemas: Some(Box::new(if exclusive {
+                SubschemaValidation {
+                    one_of: Some(schemas),
+                    ..Default::default()
+                }
+            } else {
+                SubschemaValidation {
+                    any_of: Some(schemas),
+                    ..Default::default()
+                }
+            })),
+            ..Default::default()
+        }
+        .into(),
+    }
+}
+
+fn end_schema() -> SchemaObject {
+    SchemaObject {
+        const_value: Some((&[] as &[()]).into()),
+        ..Default::default()
+    }
+}
+
+/// Schema for the encodings of inputs leading through `t`, referring to the definitions of states
+/// named in `names`.
+fn transition_to_schema(
+    t: &Transition,
+    names: &[String],
+    draft: Draft,
+    bytes: bool,
+) -> SchemaObject {
+    match t {
+        Transition::Goto(target) => reference(&names[*target], draft),
+        Transition::Consume(c, target) => consume_schema(
+            SchemaObject {
+                const_value: Some(if bytes {
+                    (*c as u32).into()
+                } else {
+                    c.to_string().into()
+                }),
+                ..Default::default()
+            },
+            &names[*target],
+            draft,
+        ),
+        Transition::ConsumeClass(class, target) => {
+            consume_schema(class_schema(&class.0, bytes), &names[*target], draft)
+        }
+        Transition::Assert(..) => unreachable!("assertions are removed before generating schema"),
+        Transition::Accept => end_schema(),
+    }
+}
+
+/// Schema for a single step of the encoded input: a pair of a character matching `input` and the
+/// rest of the input, which has to be accepted by the definition `target`.
+fn consume_schema(input: SchemaObject, target: &str, draft: Draft) -> SchemaObject {
+    tuple_schema(vec![input.into(), reference(target, draft).into()], draft)
+}
+
+/// Schema for an array with exactly the given items.
+fn tuple_schema(items: Vec<Schema>, draft: Draft) -> SchemaObject {
+    let len = items.len() as u32;
+    let mut schema = SchemaObject {
+        instance_type: Some(InstanceType::Array.into()),
+        array: Some(Box::new(ArrayValidation {
+            min_items: Some(len),
+            max_items: Some(len),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    let array = schema.array.as_mut().unwrap();
+    match draft {
+        Draft::Draft7 | Draft::Draft201909 => {
+            array.items = Some(items.into());
+            array.additional_items = Some(Box::new(false.into()));
+        }
+        // 2020-12 replaced the array form of `items` with `prefixItems`, and `additionalItems`
+        // with `items`. schemars doesn't know `prefixItems`, so it goes in as an extension.
+        Draft::Draft202012 => {
+            array.items = Some(Schema::Bool(false).into());
+            schema.extensions.insert(
+                "prefixItems".to_string(),
+                serde_json::to_value(items).unwrap(),
+            );
+        }
+    }
+    schema
+}
+
+fn const_schema(value: impl Into<serde_json::Value>) -> Schema {
+    SchemaObject {
+        const_value: Some(value.into()),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Schema for `Encoding::Tree`. The definition `T{p}_{q}` accepts the trees of non-empty inputs
+/// leading from state `p` to state `q`; only the definitions reachable from the root are emitted.
+/// With `Options::group_names`, definitions within a named capture group are prefixed with its
+/// name, as described in `state_names`.
+///
+/// Since every tree node names its split state, the alternatives of each definition are exclusive
+/// and a validator never has to backtrack.
+fn tree_schema(nfa: &Nfa, options: &Options) -> RootSchema {
+    let (draft, bytes, group_names) = (options.draft, options.bytes, options.group_names);
+    let reachable = reachability(nfa);
+    let mut queued = HashSet::new();
+    let mut queue = VecDeque::new();
+    let mut tree_ref = |p: State, q: State, queue: &mut VecDeque<_>| -> Schema {
+        if queued.insert((p, q)) {
+            queue.push_back((p, q));
+        }
+        reference(&tree_name(nfa, p, q, group_names), draft).into()
+    };
+
+    let start = nfa.start();
+    let mut roots = vec![];
+    for end in (0..nfa.num_states()).filter(|&s| nfa.is_accepting(s)) {
+        let mut trees = vec![];
+        if end == start {
+            trees.push(end_schema().into());
+        }
+        if reachable[start][end] {
+            trees.push(tree_ref(start, end, &mut queue));
+        }
+        if !trees.is_empty() {
+            roots.push(
+                tuple_schema(vec![const_schema(end), alternatives(trees, true)], draft).into(),
+            );
+        }
+    }
+
+    let mut definitions = Map::new();
+    while let Some((p, q)) = queue.pop_front() {
+        let mut leaf = ClassUnicode::empty();
+        for t in nfa.transitions(p) {
+            if t.target() == Some(q) {
+                leaf.union(&t.unicode_class().unwrap());
+            }
+        }
+        let mut schemas = vec![];
+        if !leaf.ranges().is_empty() {
+            schemas.push(class_schema(&leaf, bytes).into());
+        }
+        for (split, reachable_from_split) in reachable.iter().enumerate() {
+            if reachable[p][split] && reachable_from_split[q] {
+                schemas.push(
+                    tuple_schema(
+                        vec![
+                            const_schema(split),
+                            tree_ref(p, split, &mut queue),
+                            tree_ref(split, q, &mut queue),
+                        ],
+                        draft,
+                    )
+                    .into(),
+                );
+            }
+        }
+        definitions.insert(
+            tree_name(nfa, p, q, group_names),
+            alternatives(schemas, true),
+        );
+    }
+
+    root_schema(alternatives(roots, true), definitions, draft)
+}
+
+/// A lower bound on the serialized size of a split alternative in the tree schema: a tuple of a
+/// state number and two references.
+const MIN_SPLIT_BYTES: usize = 64;
+
+/// Check that the schema `tree_schema` would build for `nfa` is within `options.max_schema_bytes`,
+/// without building it. The schema has an alternative for every split state of every pair of
+/// states it defines, so it can grow with the cube of the number of states, and building it
+/// before `check_size` could run out of memory. The alternatives are counted in the order
+/// `tree_schema` visits them, stopping as soon as they can't fit.
+pub(crate) fn check_tree_size(nfa: &Nfa, options: &Options) -> Result<(), Error> {
+    let limit = options.max_schema_bytes;
+    let too_large = Err(Error::TooLarge {
+        what: Limit::SchemaBytes,
+        limit,
+    });
+    // The reachability matrix alone takes a byte for each pair of states.
+    if nfa.num_states().saturating_mul(nfa.num_states()) > limit {
+        return too_large;
+    }
+    let reachable = reachability(nfa);
+    let start = nfa.start();
+    let mut queued: HashSet<(State, State)> = (0..nfa.num_states())
+        .filter(|&end| nfa.is_accepting(end) && reachable[start][end])
+        .map(|end| (start, end))
+        .collect();
+    let mut queue: VecDeque<_> = queued.iter().copied().collect();
+    let mut bytes = 0;
+    while let Some((p, q)) = queue.pop_front() {
+        for (split, reachable_from_split) in reachable.iter().enumerate() {
+            if reachable[p][split] && reachable_from_split[q] {
+                bytes += MIN_SPLIT_BYTES;
+                if bytes > limit {
+                    return too_large;
+                }
+                for pair in [(p, split), (split, q)] {
+                    if queued.insert(pair) {
+                        queue.push_back(pair);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// For each pair of states `p` and `q`, whether `q` can be reached from `p` by consuming at least
+/// one character.
+fn reachability(nfa: &Nfa) -> Vec<Vec<bool>> {
+    (0..nfa.num_states())
+        .map(|p| {
+            let mut reachable = vec![false; nfa.num_states()];
+            let mut stack = vec![p];
+            while let Some(s) = stack.pop() {
+                for target in nfa.transitions(s).iter().filter_map(Transition::target) {
+                    if !reachable[target] {
+                        reachable[target] = true;
+                        stack.push(target);
+                    }
+                }
+            }
+            reachable
+        })
+        .collect()
+}
+
+/// The name of the definition of trees from `p` to `q`, with the name of the capture group both
+/// states are in, if any, as a prefix.
+fn tree_name(nfa: &Nfa, p: State, q: State, group_names: bool) -> String {
+    match (nfa.group_name(p), nfa.group_name(q)) {
+        (Some(a), Some(b)) if group_names && a == b => format!("{}_T{}_{}", a, p, q),
+        _ => format!("T{}_{}", p, q),
+    }
+}
+
+/// Classes with at most this many characters are emitted as an `enum`; larger ones as a `pattern`.
+const MAX_ENUM_CLASS_SIZE: u32 = 16;
+
+/// The astral characters of a class are emitted as an `enum`, or as alternatives in ECMA-262
+/// patterns, if there are at most this many.
+pub(crate) const MAX_ASTRAL_ENUM_SIZE: u32 = 1024;
+
+/// Schema for a single character from `class`, or with `bytes`, for the value of a byte from it.
+fn class_schema(class: &ClassUnicode, bytes: bool) -> SchemaObject {
+    let size = class_size(class);
+    if bytes {
+        return if size <= MAX_ENUM_CLASS_SIZE {
+            SchemaObject {
+                enum_values: Some(
+                    class
+                        .iter()
+                        .flat_map(|r| r.start() as u32..=r.end() as u32)
+                        .map(Into::into)
+                        .collect(),
+                ),
+                ..Default::default()
+            }
+        } else {
+            alternatives(
+                class
+                    .iter()
+                    .map(|r| byte_range_schema(r.start() as u32, r.end() as u32))
+                    .collect(),
+                true,
+            )
+            .into_object()
+        };
+    }
+    let mut negated = class.clone();
+    negated.negate();
+    if negated.ranges().is_empty() {
+        return single_char_schema();
+    }
+    if size <= MAX_ENUM_CLASS_SIZE {
+        return enum_schema(class);
+    }
+    // ECMA-262 patterns can only match astral characters by code point with the `u` flag, which
+    // not all validators set, so they're kept out of patterns where possible.
+    let (bmp, astral) = split_astral(class);
+    if astral.ranges().is_empty() {
+        pattern_object(&bmp)
+    } else if astral == all_astral() {
+        // All characters but some BMP ones, which the pattern rules out.
+        let mut schema = single_char_schema();
+        schema.subschemas().not = Some(Box::new(pattern_object(&negated).into()));
+        schema
+    } else if class_size(&astral) <= MAX_ASTRAL_ENUM_SIZE {
+        let mut schemas = vec![];
+        if !bmp.ranges().is_empty() {
+            schemas.push(class_schema(&bmp, false).into());
+        }
+        schemas.push(enum_schema(&astral).into());
+        alternatives(schemas, true).into_object()
+    } else {
+        // There's no other way to write large sets of astral characters, so validators have to
+        // set the `u` flag or match code points for these (see `compile`).
+        pattern_object(class)
+    }
+}
+
+/// The BMP and the astral characters of `class`.
+pub(crate) fn split_astral(class: &ClassUnicode) -> (ClassUnicode, ClassUnicode) {
+    let mut astral = all_astral();
+    astral.intersect(class);
+    let mut bmp = class.clone();
+    bmp.difference(&all_astral());
+    (bmp, astral)
+}
+
+/// The characters outside the BMP.
+pub(crate) fn all_astral() -> ClassUnicode {
+    ClassUnicode::new([ClassUnicodeRange::new('\u{10000}', char::MAX)])
+}
+
+/// The number of characters in `class`.
+pub(crate) fn class_size(class: &ClassUnicode) -> u32 {
+    class
+        .iter()
+        .map(|r| r.end() as u32 - r.start() as u32 + 1)
+        .sum()
+}
+
+/// Schema for any single character.
+fn single_char_schema() -> SchemaObject {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        string: Some(Box::new(StringValidation {
+            min_length: Some(1),
+            max_length: Some(1),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+/// Schema for a single character from `class`, listing them all.
+fn enum_schema(class: &ClassUnicode) -> SchemaObject {
+    SchemaObject {
+        enum_values: Some(
+            class
+                .iter()
+                .flat_map(|r| r.start()..=r.end())
+                .map(|c| c.to_string().into())
+                .collect(),
+        ),
+        ..Default::default()
+    }
+}
+
+/// Schema for a single character from `class`, as a pattern.
+fn pattern_object(class: &ClassUnicode) -> SchemaObject {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        string: Some(Box::new(StringValidation {
+            pattern: Some(format!("^{}$", class_pattern(class))),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+fn byte_range_schema(start: u32, end: u32) -> Schema {
+    if start == end {
+        return const_schema(start);
+    }
+    SchemaObject {
+        instance_type: Some(InstanceType::Integer.into()),
+        number: Some(Box::new(NumberValidation {
+            minimum: Some(start.into()),
+            maximum: Some(end.into()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Schema for plain strings matching the ECMA-262 `pattern`.
+pub fn pattern_schema(pattern: &str, draft: Draft) -> RootSchema {
+    RootSchema {
+        meta_schema: Some(draft.meta_schema().to_string()),
+        schema: SchemaObject {
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some(pattern.to_string()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        },
+        definitions: Map::new(),
+    }
+}
+
+/// Render the class as an ECMA-262 character class. A class of BMP characters means the same with
+/// and without the `u` flag: it's never negated, since a negated class would match astral
+/// characters with the flag and halves of their surrogate pairs without it, and for the same
+/// reason, ranges leave out surrogates. Astral characters are written as they are, and only work
+/// with the flag.
+pub(crate) fn class_pattern(class: &ClassUnicode) -> String {
+    format!("[{}]", class_ranges(class))
+}
+
+/// The ranges of `class`, as written between the brackets of `class_pattern`.
+pub(crate) fn class_ranges(class: &ClassUnicode) -> String {
+    let mut out = String::new();
+    for r in class.iter() {
+        let (start, end) = (r.start(), r.end());
+        if start <= '\u{d7ff}' && end >= '\u{e000}' {
+            push_class_range(&mut out, start, '\u{d7ff}');
+            push_class_range(&mut out, '\u{e000}', end);
+        } else {
+            push_class_range(&mut out, start, end);
+        }
+    }
+    out
+}
+
+fn push_class_range(out: &mut String, start: char, end: char) {
+    escape_class_char(out, start);
+    if end != start {
+        if end as u32 > start as u32 + 1 {
+            out.push('-');
+        }
+        escape_class_char(out, end);
+    }
+}
+
+fn escape_class_char(out: &mut String, c: char) {
+    escape_char(out, c, "\\][^-")
+}
+
+/// Write `c` as it should appear in an ECMA-262 regex, escaping it if it's one of `metacharacters`
+/// or a non-printable or non-ASCII BMP character. `\u{...}` escapes need the `u` flag, so astral
+/// characters are written as they are, which outside of a class matches them either way.
+pub(crate) fn escape_char(out: &mut String, c: char, metacharacters: &str) {
+    match c {
+        _ if metacharacters.contains(c) => {
+            out.push('\\');
+            out.push(c);
+        }
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        ' '..='~' => out.push(c),
+        '\0'..='\u{ffff}' => out.push_str(&format!("\\u{:04x}", c as u32)),
+        _ => out.push(c),
+    }
+}
+
+/// The name of the definition of each state `n`: `S{n}`, prefixed with the name of the capture
+/// group the state is in, if any, with `group_names`.
+fn state_names(nfa: &Nfa, group_names: bool) -> Vec<String> {
+    (0..nfa.num_states())
+        .map(|s| match nfa.group_name(s) {
+            Some(group) if group_names => format!("{}_S{}", group, s),
+            _ => format!("S{}", s),
+        })
+        .collect()
+}
@@ -0,0 +1,205 @@
+//! Tests of the command-line tool, run as a separate process.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Run the tool with `args` and `stdin`, returning its exit status, stdout and stderr.
+fn run(args: &[&str], stdin: &str) -> (i32, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_regex-to-json-schema"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn check_reports_agreement() {
+    let (status, stdout, _) = run(&["check", "a+b", "aab", "ba"], "");
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "valid\t\"aab\"\ninvalid\t\"ba\"\n");
+
+    // A comment at the end of the regex doesn't swallow the anchors `check` adds.
+    let (status, stdout, _) = run(&["check", "(?x) a b # c", "ab", "a b"], "");
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "valid\t\"ab\"\ninvalid\t\"a b\"\n");
+
+    let (status, stdout, _) = run(&["check", "--captures", "(?P<x>a)|b", "a", "c"], "");
+    assert_eq!(status, 0);
+    assert_eq!(
+        stdout,
+        "valid\t\"a\"\t{\"0\":{\"start\":0,\"end\":1,\"text\":\"a\"},\"x\":{\"start\":0,\"end\":1,\
+         \"text\":\"a\"}}\ninvalid\t\"c\"\tnull\n"
+    );
+}
+
+#[test]
+fn check_reports_disagreement() {
+    // U+1C89 is a letter since Unicode 16, which the regex crate knows about but the version of
+    // `regex_syntax` the schema is generated with doesn't.
+    let (status, stdout, stderr) = run(&["check", r"\pL", "a", "\u{1C89}"], "");
+    assert_eq!(status, 7);
+    assert_eq!(
+        stdout,
+        "valid\t\"a\"\ninvalid\t\"\u{1C89}\"\tregex crate disagrees\n"
+    );
+    assert!(stderr.contains("disagrees with the schema on 1 of 2 inputs"));
+}
+
+#[test]
+fn check_reports_regexes_the_regex_crate_rejects() {
+    let (status, stdout, stderr) = run(&["check", r"\w{500}", "a"], "");
+    assert_eq!(status, 8);
+    assert_eq!(stdout, "");
+    assert!(
+        stderr.contains("the regex crate rejects the regex"),
+        "{}",
+        stderr
+    );
+
+    // Errors in the regex itself are reported as for the other commands.
+    let (status, _, _) = run(&["check", "(a", "a"], "");
+    assert_eq!(status, 3);
+}
+
+#[test]
+fn check_validates_patterns_of_classes_with_astral_characters() {
+    let (status, stdout, stderr) = run(&["check", "--pattern", r"\d{2}-.", "12-😀", "1-x"], "");
+    assert_eq!(status, 0, "{}", stderr);
+    assert_eq!(stdout, "valid\t\"12-😀\"\ninvalid\t\"1-x\"\n");
+    assert_eq!(stderr, "");
+}
+
+/// Write `contents` to a file named `name` in a scratch directory, returning its path.
+fn scratch_file(name: &str, contents: &str) -> String {
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn arguments_are_read_from_stdin_and_files_without_their_newline() {
+    let (status, expected, _) = run(&["encode-input", "ab"], "");
+    assert_eq!(status, 0);
+    assert_eq!(expected, "[\"a\",[\"b\",[]]]\n");
+    for stdin in ["ab\n", "ab\r\n", "ab"] {
+        assert_eq!(
+            run(&["encode-input", "-"], stdin),
+            (0, expected.clone(), "".into())
+        );
+    }
+    let file = scratch_file("argument", "ab\n");
+    assert_eq!(
+        run(&["encode-input", "--file", &file], ""),
+        (0, expected, "".into())
+    );
+
+    // Only the last newline is left out.
+    let (status, stdout, _) = run(&["encode-input", "-"], "a\n\n");
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "[\"a\",[\"\\n\",[]]]\n");
+
+    // `-` reads the regex of `check` from stdin.
+    let (status, stdout, _) = run(&["check", "-", "ab", "a"], "ab\r\n");
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "valid\t\"ab\"\ninvalid\t\"a\"\n");
+}
+
+#[test]
+fn check_reads_inputs_from_a_file() {
+    let file = scratch_file("inputs", "ab\nb\n");
+    let (status, stdout, _) = run(&["check", "a?b", "a", "--inputs-file", &file], "");
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "invalid\t\"a\"\nvalid\t\"ab\"\nvalid\t\"b\"\n");
+
+    let (status, stdout, _) = run(&["check", "a?b", "--inputs-file", "-"], "b\nba\n");
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "valid\t\"b\"\ninvalid\t\"ba\"\n");
+}
+
+#[test]
+fn batch_writes_a_schema_per_line() {
+    let (status, stdout, stderr) = run(&["batch"], "a\n\n(b\nc\n");
+    assert_eq!(status, 3);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", stdout);
+    assert!(lines[0].starts_with('{'));
+    assert_eq!(lines[1], "null");
+    assert!(lines[2].starts_with('{'));
+    // Blank lines are skipped, but count towards the line numbers naming the regexes.
+    assert!(stderr.starts_with("error in 3: "), "{}", stderr);
+}
+
+#[test]
+fn batch_reads_json_lines() {
+    let stdin = "{\"name\": \"x\", \"regex\": \"a\"}\n\"b\"\n{\"regex\": 1}\n[]\n";
+    let (status, stdout, stderr) = run(
+        &[
+            "batch",
+            "--input-format",
+            "json-lines",
+            "--output-format",
+            "document",
+        ],
+        stdin,
+    );
+    assert_eq!(status, 1);
+    let document: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let names: Vec<&String> = document.as_object().unwrap().keys().collect();
+    assert_eq!(names, ["2", "x"]);
+    assert_eq!(
+        stderr,
+        "error on line 3: expected `regex` to be a string\n\
+         error on line 4: expected a string or an object\n"
+    );
+
+    let (status, _, stderr) = run(&["batch", "--input-format", "json-lines"], "{\"name\"\n");
+    assert_eq!(status, 1);
+    assert!(stderr.starts_with("error on line 1: "), "{}", stderr);
+}
+
+#[test]
+fn batch_rejects_duplicate_names() {
+    let stdin = "{\"name\": \"x\", \"regex\": \"a\"}\n{\"name\": \"x\", \"regex\": \"b\"}\n";
+    let (status, stdout, stderr) = run(
+        &[
+            "batch",
+            "--input-format",
+            "json-lines",
+            "--output-format",
+            "combined",
+        ],
+        stdin,
+    );
+    assert_eq!(status, 1);
+    assert_eq!(stderr, "error in x: duplicate name\n");
+    let schema: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let fields: Vec<&String> = schema["properties"].as_object().unwrap().keys().collect();
+    assert_eq!(fields, ["x"]);
+
+    // A name that failed to convert can be used again.
+    let stdin = "{\"name\": \"x\", \"regex\": \"(\"}\n{\"name\": \"x\", \"regex\": \"b\"}\n";
+    let (status, stdout, _) = run(&["batch", "--input-format", "json-lines"], stdin);
+    assert_eq!(status, 3);
+    assert!(stdout.starts_with("null\n{"), "{}", stdout);
+}
+
+#[test]
+fn batch_exits_with_the_status_of_the_first_error() {
+    assert_eq!(run(&["batch"], "(a\na{100000}\n").0, 3);
+    assert_eq!(run(&["batch"], "a{100000}\n(a\n").0, 5);
+    assert_eq!(run(&["batch"], "a\nb\n").0, 0);
+}
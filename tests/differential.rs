@@ -0,0 +1,120 @@
+//! Differential tests: schemas generated for random regexes must accept exactly the encoded inputs
+//! the `regex` crate matches, and `CaptureMatcher` must find the same capture groups as it does.
+
+use proptest::prelude::*;
+use proptest::test_runner::FileFailurePersistence;
+use regex_to_json_schema::{
+    generate_nfa, to_json_schema, CaptureMatcher, Encoder, Encoding, MatchMode, Options,
+};
+
+/// Regexes over a small alphabet, using every construct the NFA supports.
+fn regex() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        Just(String::new()),
+        Just("a".to_string()),
+        Just("b".to_string()),
+        Just("é".to_string()),
+        Just("[ab]".to_string()),
+        Just("[^a]".to_string()),
+        Just(r"(?-u:\w)".to_string()),
+        Just(".".to_string()),
+        Just("^".to_string()),
+        Just("$".to_string()),
+        Just("(?m:^)".to_string()),
+        Just("(?m:$)".to_string()),
+        Just(r"\b".to_string()),
+        Just(r"\B".to_string()),
+        Just(r"(?-u:\b)".to_string()),
+    ];
+    leaf.prop_recursive(4, 16, 2, |inner| {
+        prop_oneof![
+            (inner.clone(), inner.clone()).prop_map(|(x, y)| format!("{}{}", x, y)),
+            (inner.clone(), inner.clone()).prop_map(|(x, y)| format!("(?:{}|{})", x, y)),
+            inner.clone().prop_map(|x| format!("({})", x)),
+            (
+                inner.clone(),
+                prop_oneof![Just("*"), Just("+"), Just("?"), Just("*?")]
+            )
+                .prop_map(|(x, op)| format!("(?:{}){}", x, op)),
+            (inner, 0..3u32, 0..2u32).prop_map(|(x, n, extra)| format!(
+                "(?:{}){{{},{}}}",
+                x,
+                n,
+                n + extra
+            )),
+        ]
+    })
+}
+
+fn options() -> impl Strategy<Value = Options> {
+    (
+        any::<[bool; 5]>(),
+        prop_oneof![Just(MatchMode::Full), Just(MatchMode::Search)],
+        prop_oneof![
+            Just(Encoding::Cons),
+            Just(Encoding::Chunked),
+            Just(Encoding::Tree)
+        ],
+    )
+        .prop_map(|(flags, match_mode, encoding)| {
+            let [case_insensitive, multi_line, dot_matches_new_line, determinize, minimize] = flags;
+            let mut options = Options::default();
+            options.case_insensitive = case_insensitive;
+            options.multi_line = multi_line;
+            options.dot_matches_new_line = dot_matches_new_line;
+            options.determinize = determinize;
+            options.minimize = minimize;
+            options.match_mode = match_mode;
+            options.encoding = encoding;
+            options
+        })
+}
+
+proptest! {
+    // Shrunk failures are replayed before new cases, so they stay covered once fixed.
+    #![proptest_config(ProptestConfig {
+        failure_persistence: Some(Box::new(FileFailurePersistence::Direct(
+            "tests/differential.proptest-regressions",
+        ))),
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn schema_agrees_with_regex_crate(
+        regex in regex(),
+        inputs in prop::collection::vec("[abAB é\n]{0,6}", 1..8),
+        options in options(),
+    ) {
+        let nfa = generate_nfa(&regex, &options).unwrap();
+        let schema = serde_json::to_value(to_json_schema(&nfa, &options)).unwrap();
+        let validator = jsonschema::JSONSchema::compile(&schema).unwrap();
+        let expected = regex::RegexBuilder::new(&match options.match_mode {
+            MatchMode::Full => format!(r"\A(?:{})\z", regex),
+            MatchMode::Search => regex.clone(),
+        })
+        .case_insensitive(options.case_insensitive)
+        .multi_line(options.multi_line)
+        .dot_matches_new_line(options.dot_matches_new_line)
+        .build()
+        .unwrap();
+        let encoder = Encoder::new(&regex, &options).unwrap();
+        let matcher = CaptureMatcher::new(&regex, &options).unwrap();
+        for input in inputs {
+            let encoded = encoder.encode(&input);
+            prop_assert_eq!(
+                validator.is_valid(&encoded),
+                expected.is_match(&input),
+                "input {:?}",
+                input
+            );
+            let spans = matcher
+                .captures(&encoded)
+                .unwrap()
+                .map(|c| c.groups.into_iter().map(|g| g.span).collect::<Vec<_>>());
+            let expected_spans = expected
+                .captures(&input)
+                .map(|c| c.iter().map(|m| m.map(|m| m.range())).collect());
+            prop_assert_eq!(spans, expected_spans, "captures of input {:?}", input);
+        }
+    }
+}